@@ -0,0 +1,171 @@
+#[macro_use]
+extern crate criterion;
+extern crate baffles;
+
+use baffles::blocked::DefaultBlockedBloom;
+use baffles::bloom::*;
+use baffles::standard::DefaultStandardBloom;
+use criterion::{BenchmarkId, Criterion};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// The estimated set sizes to benchmark each filter at.
+const SIZES: [usize; 3] = [1024, 64 * 1024, 1024 * 1024];
+
+/// The number of bits per member used by every filter.
+const C: usize = 16;
+
+/// The number of blocks used by every blocked filter.
+const B: usize = 64;
+
+/// The number of timed rounds, and calls per round, used to compare two
+/// ways of doing the same work outside of Criterion.
+const ROUNDS: usize = 7;
+const ROUND_CALLS: usize = 100_000;
+
+fn standard_filled(n: usize) -> DefaultStandardBloom<usize> {
+    let mut bf = DefaultStandardBloom::new(n, C, optimal_hashers(C));
+    for i in 0..n {
+        bf.mark(&i);
+    }
+    bf
+}
+
+fn blocked_filled(n: usize) -> DefaultBlockedBloom<usize> {
+    let mut bf = DefaultBlockedBloom::new(n, C, optimal_hashers(C), B);
+    for i in 0..n {
+        bf.mark(&i);
+    }
+    bf
+}
+
+/// The time taken by the fastest of `ROUNDS` rounds of `ROUND_CALLS`
+/// calls to `f`. Taking the fastest round keeps a busy machine from
+/// skewing a comparison.
+fn fastest_round<F: FnMut()>(mut f: F) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..ROUND_CALLS {
+                f();
+            }
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn construction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("new");
+    for n in SIZES.iter() {
+        group.bench_with_input(BenchmarkId::new("standard", n), n, |b, &n| {
+            b.iter(|| DefaultStandardBloom::<usize>::new(n, C, optimal_hashers(C)))
+        });
+        group.bench_with_input(BenchmarkId::new("blocked", n), n, |b, &n| {
+            b.iter(|| DefaultBlockedBloom::<usize>::new(n, C, optimal_hashers(C), B))
+        });
+    }
+    group.finish();
+}
+
+fn mark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mark");
+    for n in SIZES.iter() {
+        group.bench_with_input(BenchmarkId::new("standard", n), n, |b, &n| {
+            let mut bf = DefaultStandardBloom::new(n, C, optimal_hashers(C));
+            let mut i = 0;
+            b.iter(|| {
+                bf.mark(&i);
+                i += 1;
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("blocked", n), n, |b, &n| {
+            let mut bf = DefaultBlockedBloom::new(n, C, optimal_hashers(C), B);
+            let mut i = 0;
+            b.iter(|| {
+                bf.mark(&i);
+                i += 1;
+            })
+        });
+    }
+    group.finish();
+}
+
+fn check(c: &mut Criterion) {
+    let mut group = c.benchmark_group("check");
+    for n in SIZES.iter() {
+        let standard = standard_filled(*n);
+        let blocked = blocked_filled(*n);
+
+        // Items below `n` were marked, so they're hits. Items at or
+        // above `n` weren't, so they're almost all misses.
+        group.bench_with_input(BenchmarkId::new("standard-hit", n), n, |b, &n| {
+            b.iter(|| standard.check(&(n / 2)))
+        });
+        group.bench_with_input(BenchmarkId::new("standard-miss", n), n, |b, &n| {
+            b.iter(|| standard.check(&(n * 2)))
+        });
+        group.bench_with_input(BenchmarkId::new("blocked-hit", n), n, |b, &n| {
+            b.iter(|| blocked.check(&(n / 2)))
+        });
+        group.bench_with_input(BenchmarkId::new("blocked-miss", n), n, |b, &n| {
+            b.iter(|| blocked.check(&(n * 2)))
+        });
+    }
+    group.finish();
+}
+
+/// `hash_into` reuses its output buffer, so it must beat `bit_indices`,
+/// which allocates a new list for every item. Criterion doesn't hand
+/// back its measurements, so the two are timed again directly, and the
+/// benchmark panics if the allocation-free path has regressed.
+fn hashing(c: &mut Criterion) {
+    let bf: DefaultStandardBloom<usize> = DefaultStandardBloom::new(1024, C, optimal_hashers(C));
+    let mut buf = Vec::with_capacity(bf.hash_count());
+
+    let mut group = c.benchmark_group("hash");
+    group.bench_function("bit_indices", |b| b.iter(|| bf.bit_indices(&100)));
+    group.bench_function("hash_into", |b| b.iter(|| bf.hash_into(&100, &mut buf)));
+    group.finish();
+
+    let allocating = fastest_round(|| {
+        black_box(bf.bit_indices(black_box(&100)));
+    });
+    let reusing = fastest_round(|| bf.hash_into(black_box(&100), &mut buf));
+    assert!(
+        reusing < allocating,
+        "hash_into took {:?} but bit_indices took {:?}",
+        reusing,
+        allocating
+    );
+}
+
+/// `u64` keys can skip the DefaultHasher entirely by using `mark_u64`,
+/// which mixes both the key and any rejected hashes with SplitMix64 and
+/// doesn't allocate, so it should be faster than marking them through
+/// `mark`.
+fn u64_keys(c: &mut Criterion) {
+    let n = 64 * 1024;
+
+    let mut group = c.benchmark_group("u64");
+    group.bench_function("mark", |b| {
+        let mut bf: DefaultStandardBloom<u64> = DefaultStandardBloom::new(n, C, optimal_hashers(C));
+        let mut i = 0;
+        b.iter(|| {
+            bf.mark(&i);
+            i += 1;
+        })
+    });
+    group.bench_function("mark_u64", |b| {
+        let mut bf: DefaultStandardBloom<u64> = DefaultStandardBloom::new(n, C, optimal_hashers(C));
+        let mut i = 0;
+        b.iter(|| {
+            bf.mark_u64(i);
+            i += 1;
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, construction, mark, check, hashing, u64_keys);
+criterion_main!(benches);
@@ -3,6 +3,10 @@ extern crate baffles;
 use baffles::bloom::*;
 use baffles::standard::DefaultStandardBloom;
 use baffles::blocked::DefaultBlockedBloom;
+#[cfg(feature = "ahash")]
+use baffles::standard::AHashStandardBloom;
+#[cfg(feature = "ahash")]
+use std::time::Instant;
 
 #[derive(Debug)]
 struct RunResult {
@@ -13,6 +17,7 @@ struct RunResult {
     k: usize,
 
     false_positives: usize,
+    bytes_per_member: f64,
 }
 
 fn main() {
@@ -26,25 +31,49 @@ fn main() {
     let def_standard_runs = (0..runs).map(|_| run(&mut DefaultStandardBloom::new(n, c, k)));
     let def_blocked_runs = (0..runs).map(|_| run(&mut DefaultBlockedBloom::new(n, c, k, b)));
 
-    let c = def_standard_runs.chain(def_blocked_runs);
+    let all_runs = def_standard_runs.chain(def_blocked_runs);
 
-    for r in c {
+    for r in all_runs {
         let fp = r.false_positives as f64 / r.n as f64;
         let fpp = false_positive_probability(r.n, r.c, r.k);
 
         let abs_diff = fp - fpp;
 
         println!(
-            "{:>10}: {:5} out of {} checks were false positives. \
-             This rate is {:.7} with an expected rate of {:.7}. (diff: {:>+12.7})",
+            "{:>26}: {:5} out of {} checks were false positives. \
+             This rate is {:.7} with an expected rate of {:.7}. (diff: {:>+12.7}) \
+             {:.3} bytes per member.",
             r.name,
             r.false_positives,
             r.n,
             fp,
             fpp,
             abs_diff,
+            r.bytes_per_member,
         );
     }
+
+    #[cfg(feature = "ahash")]
+    compare_hashers(n, c, k);
+}
+
+/// Time how long it takes to mark and check `n` items with both the
+/// DefaultHasher and aHash.
+#[cfg(feature = "ahash")]
+fn compare_hashers(n: usize, c: usize, k: usize) {
+    let start = Instant::now();
+    run(&mut DefaultStandardBloom::new(n, c, k));
+    let default_time = start.elapsed();
+
+    let start = Instant::now();
+    run(&mut AHashStandardBloom::new(n, c, k));
+    let ahash_time = start.elapsed();
+
+    println!(
+        "DefaultHasher took {:?} and aHash took {:?} for the same run.",
+        default_time,
+        ahash_time,
+    );
 }
 
 fn run<B: BloomFilter<usize>>(bf: &mut B) -> RunResult {
@@ -79,10 +108,11 @@ fn run<B: BloomFilter<usize>>(bf: &mut B) -> RunResult {
     });
 
     RunResult {
-        name: bf.name().to_string(),
-        n: n,
-        c: c,
-        k: k,
-        false_positives: false_positives,
+        name: bf.full_name(),
+        n,
+        c,
+        k,
+        false_positives,
+        bytes_per_member: bf.bytes_per_member(),
     }
 }
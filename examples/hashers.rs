@@ -0,0 +1,49 @@
+#[cfg(feature = "ahash")]
+extern crate ahash;
+extern crate baffles;
+extern crate fnv;
+
+use baffles::bloom::*;
+use baffles::standard::StandardBloom;
+use fnv::FnvHasher;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+fn main() {
+    let n = 100 * 1000;
+    let c = 10;
+    let k = optimal_hashers(c);
+
+    println!(
+        "Filters with n={} c={} k={} should see a false positive rate of about {:.5}.",
+        n,
+        c,
+        k,
+        false_positive_probability(n, c, k)
+    );
+
+    report("DefaultHasher", observed_fp_rate::<DefaultHasher>(n, c, k));
+    report("FNV", observed_fp_rate::<FnvHasher>(n, c, k));
+
+    #[cfg(feature = "ahash")]
+    report("aHash", observed_fp_rate::<ahash::AHasher>(n, c, k));
+
+    #[cfg(not(feature = "ahash"))]
+    println!("{:>14}: skipped, build with --features ahash to include it", "aHash");
+}
+
+fn report(name: &str, fp: f64) {
+    println!("{:>14}: {:.5}", name, fp);
+}
+
+/// Mark `n` items in a filter using the hasher `H`, then return the
+/// fraction of `n` other items that the filter claims are members.
+fn observed_fp_rate<H: Hasher + Default>(n: usize, c: usize, k: usize) -> f64 {
+    let mut bf: StandardBloom<H, usize> = StandardBloom::new(n, c, k);
+    for i in 0..n {
+        bf.mark(&i);
+    }
+
+    let false_positives = (n..2 * n).filter(|i| bf.check(i)).count();
+    false_positives as f64 / n as f64
+}
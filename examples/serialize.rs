@@ -0,0 +1,38 @@
+extern crate baffles;
+
+use baffles::bloom::*;
+use baffles::standard::DefaultStandardBloom;
+use std::env;
+use std::fs;
+use std::time::Instant;
+
+fn main() {
+    let n = 100 * 1000;
+    let c = 16;
+    let k = optimal_hashers(c);
+
+    let mut bf: DefaultStandardBloom<usize> = DefaultStandardBloom::new(n, c, k);
+    for i in 0..n {
+        bf.mark(&i);
+    }
+
+    // Write the filter out to a file.
+    let path = env::temp_dir().join("baffles-serialize-example.bin");
+    let bytes = bf.to_bytes();
+    fs::write(&path, &bytes).expect("failed to write the filter");
+    println!("Serialized {} items into {} bytes at {}.", n, bytes.len(), path.display());
+
+    // Read it back in again.
+    let start = Instant::now();
+    let read = fs::read(&path).expect("failed to read the filter");
+    let reloaded: DefaultStandardBloom<usize> =
+        DefaultStandardBloom::from_bytes(&read).expect("the bytes should be a valid filter");
+    let elapsed = start.elapsed();
+    println!("Reloaded the filter in {:?}.", elapsed);
+
+    assert!((0..n).all(|i| reloaded.check(&i)));
+    assert!(reloaded == bf);
+    println!("All {} items are still members.", n);
+
+    fs::remove_file(&path).expect("failed to remove the filter");
+}
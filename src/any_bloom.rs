@@ -0,0 +1,158 @@
+//! A filter that can be any of the bloom filters in this crate, chosen
+//! at runtime. Unlike a `Box<dyn BloomFilter<T>>`, calls are dispatched
+//! with a `match` rather than through a vtable.
+
+use blocked::BlockedBloom;
+use bloom::{BloomFilter, Saturated};
+use standard::StandardBloom;
+use std::any::Any;
+use std::hash::{Hash, Hasher};
+use std;
+
+/// Either a StandardBloom or a BlockedBloom filter.
+///
+/// ```
+/// use baffles::any_bloom::*;
+/// use baffles::blocked::*;
+/// use baffles::standard::*;
+///
+/// let blocked = std::env::args().any(|a| a == "--blocked");
+/// let mut filter: DefaultAnyBloom<usize> = if blocked {
+///     AnyBloom::Blocked(BlockedBloom::new(1024, 16, 8, 4))
+/// } else {
+///     AnyBloom::Standard(StandardBloom::new(1024, 16, 8))
+/// };
+///
+/// filter.mark(&100);
+/// assert!(filter.check(&100));
+/// ```
+pub enum AnyBloom<H, T> {
+    Standard(StandardBloom<H, T>),
+    Blocked(BlockedBloom<H, T>),
+}
+
+impl<H, T> Clone for AnyBloom<H, T> {
+    fn clone(&self) -> Self {
+        match *self {
+            AnyBloom::Standard(ref f) => AnyBloom::Standard(f.clone()),
+            AnyBloom::Blocked(ref f) => AnyBloom::Blocked(f.clone()),
+        }
+    }
+}
+
+/// An AnyBloom that uses the DefaultHasher.
+pub type DefaultAnyBloom<T> = AnyBloom<std::collections::hash_map::DefaultHasher, T>;
+
+impl<H: Hasher + Default, T: Hash> BloomFilter<T> for AnyBloom<H, T> {
+    fn name(&self) -> &str {
+        match *self {
+            AnyBloom::Standard(ref f) => f.name(),
+            AnyBloom::Blocked(ref f) => f.name(),
+        }
+    }
+
+    fn mark(&mut self, item: &T) {
+        match *self {
+            AnyBloom::Standard(ref mut f) => f.mark(item),
+            AnyBloom::Blocked(ref mut f) => f.mark(item),
+        }
+    }
+
+    fn check(&self, item: &T) -> bool {
+        match *self {
+            AnyBloom::Standard(ref f) => f.check(item),
+            AnyBloom::Blocked(ref f) => f.check(item),
+        }
+    }
+
+    fn clear(&mut self) {
+        match *self {
+            AnyBloom::Standard(ref mut f) => f.clear(),
+            AnyBloom::Blocked(ref mut f) => f.clear(),
+        }
+    }
+
+    fn bulk_check(&self, items: &[T]) -> Vec<bool> {
+        match *self {
+            AnyBloom::Standard(ref f) => f.bulk_check(items),
+            AnyBloom::Blocked(ref f) => f.bulk_check(items),
+        }
+    }
+
+    fn try_mark(&mut self, item: &T) -> Result<(), Saturated> {
+        match *self {
+            AnyBloom::Standard(ref mut f) => f.try_mark(item),
+            AnyBloom::Blocked(ref mut f) => f.try_mark(item),
+        }
+    }
+
+    fn set_size(&self) -> usize {
+        match *self {
+            AnyBloom::Standard(ref f) => f.set_size(),
+            AnyBloom::Blocked(ref f) => f.set_size(),
+        }
+    }
+
+    fn bits_per_member(&self) -> usize {
+        match *self {
+            AnyBloom::Standard(ref f) => f.bits_per_member(),
+            AnyBloom::Blocked(ref f) => f.bits_per_member(),
+        }
+    }
+
+    fn hash_count(&self) -> usize {
+        match *self {
+            AnyBloom::Standard(ref f) => f.hash_count(),
+            AnyBloom::Blocked(ref f) => f.hash_count(),
+        }
+    }
+
+    fn memory_bytes(&self) -> usize {
+        match *self {
+            AnyBloom::Standard(ref f) => f.memory_bytes(),
+            AnyBloom::Blocked(ref f) => f.memory_bytes(),
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any
+    where
+        Self: 'static,
+    {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any
+    where
+        Self: 'static,
+    {
+        self
+    }
+
+    fn box_clone(&self) -> Box<dyn BloomFilter<T>>
+    where
+        Self: 'static,
+    {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatches_to_the_active_filter() {
+        let filters: Vec<DefaultAnyBloom<usize>> = vec![
+            AnyBloom::Standard(StandardBloom::new(1024, 16, 8)),
+            AnyBloom::Blocked(BlockedBloom::new(1024, 16, 8, 4)),
+        ];
+
+        for (mut filter, name) in filters.into_iter().zip(&["standard", "blocked"]) {
+            assert!(filter.name() == *name);
+            assert!(!filter.check(&100));
+            filter.mark(&100);
+            assert!(filter.check(&100));
+            assert!(filter.set_size() == 1024);
+        }
+    }
+}
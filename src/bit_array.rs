@@ -1,17 +1,91 @@
+//! A fixed-width array of bits, stored in `u64` words. This is the
+//! storage behind every StandardBloom filter, and can be created ahead
+//! of time and handed to `StandardBloom::from_bit_array`.
+
+#[cfg(feature = "mmap")]
+use memmap2::MmapMut;
 use std;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "mmap")]
+use std::io;
+use std::ops::{Deref, DerefMut};
 
-type Word = u64;
+/// The type of the words backing a BitArray.
+pub type Word = u64;
 
+/// A fixed number of bits, all clear to begin with.
 pub struct BitArray {
     bits: usize,
-    backing: Vec<Word>,
+    order: BitOrder,
+    backing: Backing,
+}
+
+/// Which end of a word bit 0 is stored at. This only changes where
+/// each bit lives within its word, never which word it's in or the
+/// logical index used to reach it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BitOrder {
+    /// Bit 0 is the least significant bit of the first word.
+    #[default]
+    LsbFirst,
+
+    /// Bit 0 is the most significant bit of the first word.
+    MsbFirst,
+}
+
+impl BitOrder {
+    /// Convert `mask`, written as though bit 0 is the least significant
+    /// bit, into this order.
+    fn arrange(self, mask: Word) -> Word {
+        match self {
+            BitOrder::LsbFirst => mask,
+            BitOrder::MsbFirst => mask.reverse_bits(),
+        }
+    }
+}
+
+/// Where the words of a BitArray are stored.
+enum Backing {
+    /// Words on the heap.
+    Owned(Vec<Word>),
+
+    /// Words in a memory-mapped file. The mapping is page aligned and a
+    /// whole number of words long.
+    #[cfg(feature = "mmap")]
+    Mapped(MmapMut),
+}
+
+impl Deref for Backing {
+    type Target = [Word];
+
+    fn deref(&self) -> &[Word] {
+        match *self {
+            Backing::Owned(ref words) => words,
+            #[cfg(feature = "mmap")]
+            Backing::Mapped(ref map) => unsafe {
+                std::slice::from_raw_parts(map.as_ptr() as *const Word, map.len() / 8)
+            },
+        }
+    }
+}
+
+impl DerefMut for Backing {
+    fn deref_mut(&mut self) -> &mut [Word] {
+        match *self {
+            Backing::Owned(ref mut words) => words,
+            #[cfg(feature = "mmap")]
+            Backing::Mapped(ref mut map) => unsafe {
+                std::slice::from_raw_parts_mut(map.as_mut_ptr() as *mut Word, map.len() / 8)
+            },
+        }
+    }
 }
 
 impl fmt::Debug for BitArray {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "BitArray {{ bits: ")?;
-        for w in &self.backing {
+        for w in self.backing.iter() {
             write!(f, "{:#016X} ", w)?;
         }
 
@@ -19,6 +93,39 @@ impl fmt::Debug for BitArray {
     }
 }
 
+/// Cloning always copies the words onto the heap, even when they're
+/// stored in a memory-mapped file.
+impl Clone for BitArray {
+    fn clone(&self) -> BitArray {
+        BitArray {
+            bits: self.bits,
+            order: self.order,
+            backing: Backing::Owned(self.as_words().to_vec()),
+        }
+    }
+}
+
+/// Two BitArrays are equal when they have the same width, the same bit
+/// order and the same bits set.
+impl PartialEq for BitArray {
+    fn eq(&self, other: &BitArray) -> bool {
+        self.bits == other.bits && self.order == other.order &&
+            self.as_words() == other.as_words()
+    }
+}
+
+impl Eq for BitArray {}
+
+/// Hashes the width, the bit order and every backing word. Padding bits are always
+/// clear, so equal BitArrays hash equally.
+impl Hash for BitArray {
+    fn hash<S: Hasher>(&self, state: &mut S) {
+        self.bits.hash(state);
+        self.order.hash(state);
+        self.as_words().hash(state);
+    }
+}
+
 fn bits_in_word() -> usize {
     8 * std::mem::size_of::<Word>()
 }
@@ -28,52 +135,354 @@ fn word_index_for_bit(bit: usize) -> usize {
 }
 
 impl BitArray {
+    /// Create a BitArray of `bit_count` bits, all clear.
     pub fn new(bit_count: usize) -> BitArray {
+        BitArray::new_with_order(bit_count, BitOrder::LsbFirst)
+    }
+
+    /// Create a BitArray of `bit_count` bits, all clear, that stores
+    /// its bits within each word in `order`.
+    pub fn new_with_order(bit_count: usize, order: BitOrder) -> BitArray {
         assert!(bit_count > 0);
 
         let max_index = bit_count - 1;
         let words_needed_for_bits = word_index_for_bit(max_index) + 1;
         BitArray {
             bits: bit_count,
-            backing: vec![0; words_needed_for_bits],
+            order,
+            backing: Backing::Owned(vec![0; words_needed_for_bits]),
+        }
+    }
+
+    /// The order bits are stored in within each word.
+    pub fn order(&self) -> BitOrder {
+        self.order
+    }
+
+    /// Create a BitArray of `bit_count` bits from its backing
+    /// words. This is the inverse of `as_words`.
+    pub fn from_words(bit_count: usize, backing: Vec<Word>) -> BitArray {
+        BitArray::from_words_with_order(bit_count, backing, BitOrder::LsbFirst)
+    }
+
+    /// Like `from_words`, for words that store their bits in `order`.
+    pub fn from_words_with_order(
+        bit_count: usize,
+        backing: Vec<Word>,
+        order: BitOrder,
+    ) -> BitArray {
+        assert!(BitArray::words_fit_with_order(bit_count, &backing, order));
+
+        BitArray {
+            bits: bit_count,
+            order,
+            backing: Backing::Owned(backing),
+        }
+    }
+
+    /// Create a BitArray of `bit_count` bits stored in a memory-mapped
+    /// file. The map must be exactly as long as the words needed for
+    /// `bit_count` bits, and any padding bits must be clear. Words are
+    /// stored in the machine's byte order.
+    #[cfg(feature = "mmap")]
+    pub fn from_mmap(bit_count: usize, map: MmapMut) -> io::Result<BitArray> {
+        let whole_words = map.len() % 8 == 0;
+        let backing = Backing::Mapped(map);
+        if !whole_words || !BitArray::words_fit(bit_count, &backing) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the mapped file doesn't match the number of bits",
+            ));
+        }
+
+        Ok(BitArray {
+            bits: bit_count,
+            order: BitOrder::LsbFirst,
+            backing,
+        })
+    }
+
+    /// Write any changes to a memory-mapped BitArray back to its file.
+    /// This does nothing for a BitArray stored on the heap.
+    #[cfg(feature = "mmap")]
+    pub fn flush(&self) -> io::Result<()> {
+        match self.backing {
+            Backing::Owned(_) => Ok(()),
+            Backing::Mapped(ref map) => map.flush(),
+        }
+    }
+
+    /// Create a `LsbFirst` BitArray of `bit_count` bits from the bytes
+    /// written by `to_packed_le_bytes`.
+    pub fn from_packed_le_bytes(bit_count: usize, bytes: &[u8]) -> BitArray {
+        assert!(bit_count > 0);
+        assert!(bytes.len() == bit_count.div_ceil(8));
+
+        let mut backing = vec![0; word_index_for_bit(bit_count - 1) + 1];
+        for (i, byte) in bytes.iter().enumerate() {
+            backing[i / 8] |= (*byte as Word) << (8 * (i % 8));
+        }
+
+        BitArray::from_words(bit_count, backing)
+    }
+
+    /// True if `backing` is a valid set of words for a `LsbFirst`
+    /// BitArray of `bit_count` bits: there are exactly enough words,
+    /// and any bits in the final word beyond `bit_count` are clear.
+    pub fn words_fit(bit_count: usize, backing: &[Word]) -> bool {
+        BitArray::words_fit_with_order(bit_count, backing, BitOrder::LsbFirst)
+    }
+
+    /// Like `words_fit`, for words that store their bits in `order`,
+    /// which decides where the padding bits of the final word are.
+    pub fn words_fit_with_order(bit_count: usize, backing: &[Word], order: BitOrder) -> bool {
+        if bit_count == 0 || backing.len() != word_index_for_bit(bit_count - 1) + 1 {
+            return false;
         }
+
+        let used_in_last = bit_count % bits_in_word();
+        let padding = order.arrange(Word::MAX << used_in_last);
+        used_in_last == 0 || backing[backing.len() - 1] & padding == 0
     }
 
+    /// Set or clear `bit` depending on `state`.
     pub fn set_to(&mut self, bit: usize, state: bool) {
         assert!(bit < self.bits);
         let word_ix = word_index_for_bit(bit);
-        let bit_ix = bit % bits_in_word();
-        let set_mask = 1 << bit_ix;
+        let set_mask = self.mask_for(bit);
 
         if state {
             self.backing[word_ix] |= set_mask;
         } else {
             self.backing[word_ix] &= !set_mask;
         }
+
+        #[cfg(debug_assertions)]
+        self.assert_padding_clear();
     }
 
-    #[allow(dead_code)]
+    /// Set `bit`.
     pub fn set(&mut self, bit: usize) {
         self.set_to(bit, true)
     }
 
-    #[allow(dead_code)]
+    /// Set `bit`, returning whether it was already set.
+    pub fn set_returning_previous(&mut self, bit: usize) -> bool {
+        assert!(bit < self.bits);
+        let word_ix = word_index_for_bit(bit);
+        let set_mask = self.mask_for(bit);
+
+        let previous = self.backing[word_ix] & set_mask == set_mask;
+        self.backing[word_ix] |= set_mask;
+        previous
+    }
+
+    /// Clear `bit`.
     pub fn clear(&mut self, bit: usize) {
         self.set_to(bit, false)
     }
 
+    /// Clear every bit.
+    pub fn clear_all(&mut self) {
+        for w in self.backing.iter_mut() {
+            *w = 0;
+        }
+    }
+
+    /// Set every bit in `start..end`. Whole words in the middle of the
+    /// range are set at once rather than bit by bit.
+    pub fn set_range(&mut self, start: usize, end: usize) {
+        assert!(start <= end);
+        assert!(end <= self.bits);
+
+        if start == end {
+            return;
+        }
+
+        let first_word = word_index_for_bit(start);
+        let last_word = word_index_for_bit(end - 1);
+
+        // A mask of the bits at or above `start` in the first word, and
+        // of the bits below `end` in the last word.
+        let low_mask = self.order.arrange(Word::MAX << (start % bits_in_word()));
+        let high_mask = self
+            .order
+            .arrange(Word::MAX >> (bits_in_word() - 1 - (end - 1) % bits_in_word()));
+
+        if first_word == last_word {
+            self.backing[first_word] |= low_mask & high_mask;
+        } else {
+            self.backing[first_word] |= low_mask;
+            for w in &mut self.backing[first_word + 1..last_word] {
+                *w = Word::MAX;
+            }
+            self.backing[last_word] |= high_mask;
+        }
+
+        #[cfg(debug_assertions)]
+        self.assert_padding_clear();
+    }
+
+    /// True if `bit` is set.
     pub fn get(&self, bit: usize) -> bool {
         assert!(bit < self.bits);
         let word_ix = word_index_for_bit(bit);
-        let bit_ix = bit % bits_in_word();
-        let set_mask = 1 << bit_ix;
+        let set_mask = self.mask_for(bit);
 
         set_mask == self.backing[word_ix] & set_mask
     }
 
+    /// The state of `bit`. This is the same as `get`.
+    pub fn at(&self, bit: usize) -> bool {
+        self.get(bit)
+    }
+
+    /// Iterate over the state of every bit, from bit 0 up to `width`.
+    pub fn bits<'a>(&'a self) -> impl Iterator<Item = bool> + 'a {
+        (0..self.bits).map(move |bit| self.get(bit))
+    }
+
+    /// The number of bits.
     pub fn width(&self) -> usize {
         self.bits
     }
+
+    /// The number of bits that are set.
+    pub fn count_ones(&self) -> usize {
+        self.backing.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// The number of bits in `start..end` that are set. Whole words in
+    /// the middle of the range are counted at once rather than bit by
+    /// bit.
+    pub fn count_ones_range(&self, start: usize, end: usize) -> usize {
+        assert!(start <= end);
+        assert!(end <= self.bits);
+
+        if start == end {
+            return 0;
+        }
+
+        let first_word = word_index_for_bit(start);
+        let last_word = word_index_for_bit(end - 1);
+
+        // The same masks as `set_range` uses to find the bits of the
+        // first and last words that are in the range.
+        let low_mask = self.order.arrange(Word::MAX << (start % bits_in_word()));
+        let high_mask = self
+            .order
+            .arrange(Word::MAX >> (bits_in_word() - 1 - (end - 1) % bits_in_word()));
+
+        if first_word == last_word {
+            (self.backing[first_word] & low_mask & high_mask).count_ones() as usize
+        } else {
+            let middle: usize = self.backing[first_word + 1..last_word]
+                .iter()
+                .map(|w| w.count_ones() as usize)
+                .sum();
+
+            (self.backing[first_word] & low_mask).count_ones() as usize + middle +
+                (self.backing[last_word] & high_mask).count_ones() as usize
+        }
+    }
+
+    /// The number of bits that are clear. Padding bits in the final
+    /// word beyond `width` are not counted.
+    pub fn count_zeros(&self) -> usize {
+        self.bits - self.count_ones()
+    }
+
+    /// The fraction of bits that are set.
+    pub fn density(&self) -> f64 {
+        self.count_ones() as f64 / self.bits as f64
+    }
+
+    /// Panic if any of the bits in the final word beyond `width` are
+    /// set. Counting and serialization rely on them always being clear.
+    #[cfg(debug_assertions)]
+    fn assert_padding_clear(&self) {
+        assert!(
+            BitArray::words_fit_with_order(self.bits, &self.backing, self.order),
+            "BitArray padding bits are set"
+        );
+    }
+
+    /// The mask selecting `bit` within its word.
+    fn mask_for(&self, bit: usize) -> Word {
+        self.order.arrange(1 << (bit % bits_in_word()))
+    }
+
+    /// Mutable access to the backing words, so tests can put the
+    /// BitArray into states that are otherwise impossible.
+    #[cfg(all(test, debug_assertions))]
+    fn backing_mut(&mut self) -> &mut [Word] {
+        &mut self.backing
+    }
+
+    /// Pack the bits into exactly `ceil(width / 8)` bytes. Bit `i` is
+    /// stored in byte `i / 8` at position `i % 8`, counting from the
+    /// least significant bit. This layout doesn't depend on the size or
+    /// endianness of the words backing the BitArray, or on its bit
+    /// order: a `MsbFirst` BitArray packs to the same bytes as a
+    /// `LsbFirst` one with the same bits set.
+    pub fn to_packed_le_bytes(&self) -> Vec<u8> {
+        let order = self.order;
+        let mut bytes: Vec<u8> = self
+            .backing
+            .iter()
+            .flat_map(|w| order.arrange(*w).to_le_bytes())
+            .collect();
+        bytes.truncate(self.bits.div_ceil(8));
+        bytes
+    }
+
+    /// Set every bit that is set in `other`. Both BitArrays must have
+    /// the same width and bit order.
+    pub fn union(&mut self, other: &BitArray) {
+        assert!(self.bits == other.bits);
+        assert!(self.order == other.order);
+        self.or_words(&other.backing);
+    }
+
+    /// Count the bits that are set in all three of `a`, `b` and `c`
+    /// without building their intersection. All three must have the
+    /// same width and bit order.
+    pub fn and3_count(a: &BitArray, b: &BitArray, c: &BitArray) -> usize {
+        assert!(a.bits == b.bits && a.bits == c.bits);
+        assert!(a.order == b.order && a.order == c.order);
+
+        a.backing
+            .iter()
+            .zip(b.backing.iter())
+            .zip(c.backing.iter())
+            .map(|((x, y), z)| (x & y & z).count_ones() as usize)
+            .sum()
+    }
+
+    /// Set every bit that is set in `words`, which are laid out like
+    /// the words from `as_words`. This allows words read from elsewhere
+    /// to be merged without building a BitArray from them first.
+    pub fn or_words(&mut self, words: &[Word]) {
+        assert!(words.len() == self.backing.len());
+
+        for (w, o) in self.backing.iter_mut().zip(words) {
+            *w |= *o;
+        }
+
+        #[cfg(debug_assertions)]
+        self.assert_padding_clear();
+    }
+
+    /// Iterate over the backing words `chunk` at a time. The last chunk
+    /// is shorter if `chunk` doesn't evenly divide the number of words.
+    pub fn word_chunks<'a>(&'a self, chunk: usize) -> impl Iterator<Item = &'a [Word]> + 'a {
+        self.backing.chunks(chunk)
+    }
+
+    /// The words backing the BitArray.
+    pub fn as_words(&self) -> &[Word] {
+        &self.backing
+    }
 }
 
 #[cfg(test)]
@@ -113,4 +522,322 @@ mod tests {
         ba.clear(0);
         assert!(!ba.get(0));
     }
+
+    #[test]
+    fn test_set_returning_previous() {
+        let mut ba = BitArray::new(100);
+
+        assert!(!ba.set_returning_previous(70));
+        assert!(ba.set_returning_previous(70));
+        assert!(ba.get(70));
+        assert!(ba.count_ones() == 1);
+    }
+
+    #[test]
+    fn test_words_round_trip() {
+        let mut ba = BitArray::new(100);
+        ba.set(0);
+        ba.set(63);
+        ba.set(99);
+
+        let rt = BitArray::from_words(ba.width(), ba.as_words().to_vec());
+        assert!(rt.width() == 100);
+        assert!(rt.as_words() == ba.as_words());
+        assert!(rt.get(0) && rt.get(63) && rt.get(99));
+        assert!(!rt.get(1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_from_words_too_short() {
+        BitArray::from_words(100, vec![0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_from_words_padding_set() {
+        BitArray::from_words(100, vec![0, 1 << 40]);
+    }
+
+    #[test]
+    fn test_eq_and_hash() {
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash_of(ba: &BitArray) -> u64 {
+            let mut h = DefaultHasher::new();
+            ba.hash(&mut h);
+            h.finish()
+        }
+
+        let mut a = BitArray::new(100);
+        let mut b = BitArray::new(100);
+        let mut c = BitArray::new(100);
+        a.set(3);
+        b.set(3);
+        c.set(4);
+
+        assert!(a == b && hash_of(&a) == hash_of(&b));
+        assert!(a != c && hash_of(&a) != hash_of(&c));
+
+        // The same words with a different width aren't equal.
+        assert!(BitArray::new(100) != BitArray::new(128));
+    }
+
+    #[test]
+    fn test_count_ones() {
+        let mut ba = BitArray::new(130);
+        assert!(ba.count_ones() == 0);
+
+        ba.set(0);
+        ba.set(64);
+        ba.set(129);
+        assert!(ba.count_ones() == 3);
+    }
+
+    #[test]
+    fn test_count_zeros_and_density() {
+        for width in &[1, 63, 64, 65, 130, 1000] {
+            let mut ba = BitArray::new(*width);
+            assert!(ba.count_zeros() == *width);
+            assert!(ba.density() == 0.0);
+
+            for bit in (0..*width).filter(|b| b % 3 == 0) {
+                ba.set(bit);
+            }
+
+            assert!(ba.count_ones() + ba.count_zeros() == *width);
+            assert!(ba.density() == ba.count_ones() as f64 / *width as f64);
+        }
+    }
+
+    #[test]
+    fn test_bits_iterator() {
+        let mut ba = BitArray::new(100);
+        ba.set(0);
+        ba.set(64);
+        ba.set(99);
+
+        assert!(ba.bits().count() == 100);
+        assert!(ba.bits().filter(|b| *b).count() == ba.count_ones());
+        assert!(ba.bits().enumerate().all(|(ix, b)| b == ba.at(ix)));
+    }
+
+    #[test]
+    fn test_count_ones_range() {
+        let mut ba = BitArray::new(250);
+        for bit in (0..250).filter(|b| b % 3 == 0 || b % 7 == 0) {
+            ba.set(bit);
+        }
+
+        let ranges = [(0, 0), (0, 1), (3, 9), (0, 64), (60, 70), (5, 200), (64, 128), (1, 250)];
+        for &(start, end) in &ranges {
+            let naive = (start..end).filter(|b| ba.get(*b)).count();
+            assert!(ba.count_ones_range(start, end) == naive);
+        }
+
+        assert!(ba.count_ones_range(0, 250) == ba.count_ones());
+    }
+
+    #[test]
+    fn test_set_range() {
+        let ranges = [(0, 0), (0, 1), (3, 9), (0, 64), (60, 70), (5, 200), (64, 128), (1, 250)];
+
+        for &(start, end) in &ranges {
+            let mut fast = BitArray::new(250);
+            let mut slow = BitArray::new(250);
+
+            fast.set_range(start, end);
+            for bit in start..end {
+                slow.set(bit);
+            }
+
+            assert!(fast.as_words() == slow.as_words());
+            assert!(fast.count_ones() == end - start);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_set_range_out_of_range() {
+        let mut ba = BitArray::new(10);
+        ba.set_range(5, 11);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "padding bits are set")]
+    fn test_padding_corruption_is_caught() {
+        let mut ba = BitArray::new(10);
+        ba.backing_mut()[0] |= 1 << 20;
+        ba.set(0);
+    }
+
+    #[test]
+    fn test_word_chunks() {
+        let mut ba = BitArray::new(1000);
+        for bit in (0..1000).filter(|b| b % 5 == 0) {
+            ba.set(bit);
+        }
+
+        for chunk in 1..20 {
+            let joined: Vec<Word> = ba.word_chunks(chunk).flat_map(|c| c.to_vec()).collect();
+            assert!(joined == ba.as_words());
+            assert!(ba.word_chunks(chunk).all(|c| c.len() <= chunk));
+        }
+    }
+
+    #[test]
+    fn test_clear_all() {
+        let mut ba = BitArray::new(130);
+        ba.set_range(3, 129);
+        ba.clear_all();
+        assert!(ba.width() == 130);
+        assert!(ba.count_ones() == 0);
+    }
+
+    #[test]
+    fn test_or_words() {
+        let mut a = BitArray::new(200);
+        let mut b = BitArray::new(200);
+        a.set_range(10, 20);
+        b.set_range(15, 150);
+
+        let mut by_union = BitArray::from_words(200, a.as_words().to_vec());
+        by_union.union(&b);
+        a.or_words(b.as_words());
+
+        assert!(a.as_words() == by_union.as_words());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_or_words_wrong_length() {
+        BitArray::new(200).or_words(&[0, 0]);
+    }
+
+    #[test]
+    fn test_union() {
+        let mut a = BitArray::new(100);
+        let mut b = BitArray::new(100);
+        a.set(1);
+        b.set(1);
+        b.set(70);
+
+        a.union(&b);
+        assert!(a.count_ones() == 2);
+        assert!(a.get(1) && a.get(70));
+    }
+
+    #[test]
+    fn test_and3_count() {
+        let mut arrays = [BitArray::new(300), BitArray::new(300), BitArray::new(300)];
+        for (step, ba) in [2, 3, 5].iter().zip(arrays.iter_mut()) {
+            for bit in (0..300).filter(|b| b % step == 0) {
+                ba.set(bit);
+            }
+        }
+
+        let mut chained = arrays[0].as_words().to_vec();
+        for ba in &arrays[1..] {
+            for (w, o) in chained.iter_mut().zip(ba.as_words()) {
+                *w &= *o;
+            }
+        }
+        let expected: usize = chained.iter().map(|w| w.count_ones() as usize).sum();
+
+        assert!(expected == 10);
+        assert!(BitArray::and3_count(&arrays[0], &arrays[1], &arrays[2]) == expected);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_and3_count_mismatched_widths() {
+        BitArray::and3_count(&BitArray::new(10), &BitArray::new(10), &BitArray::new(11));
+    }
+
+    #[test]
+    fn test_packed_le_bytes_round_trip() {
+        let mut ba = BitArray::new(200);
+        for bit in (0..200).filter(|b| b % 7 == 0) {
+            ba.set(bit);
+        }
+
+        let bytes = ba.to_packed_le_bytes();
+        assert!(bytes.len() == 25);
+
+        let rt = BitArray::from_packed_le_bytes(200, &bytes);
+        assert!(rt.as_words() == ba.as_words());
+    }
+
+    #[test]
+    fn test_packed_le_bytes_layout() {
+        let mut ba = BitArray::new(20);
+        ba.set(0);
+        ba.set(9);
+        ba.set(17);
+        ba.set(19);
+
+        assert!(ba.to_packed_le_bytes() == vec![0x01, 0x02, 0x0A]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_packed_le_bytes_padding_set() {
+        BitArray::from_packed_le_bytes(20, &[0, 0, 0x10]);
+    }
+
+    #[test]
+    fn test_msb_first_get_after_set() {
+        for &order in &[BitOrder::LsbFirst, BitOrder::MsbFirst] {
+            let mut ba = BitArray::new_with_order(150, order);
+            let marked: Vec<usize> = (0..150).filter(|b| b % 3 == 0).collect();
+            for &bit in &marked {
+                ba.set(bit);
+            }
+
+            assert!(ba.order() == order);
+            assert!((0..150).all(|b| ba.get(b) == (b % 3 == 0)));
+            assert!(ba.count_ones() == marked.len());
+            let in_range = ba.bits().skip(10).take(130).filter(|b| *b).count();
+            assert!(ba.count_ones_range(10, 140) == in_range);
+
+            ba.clear(3);
+            assert!(!ba.get(3));
+            assert!(!ba.set_returning_previous(3));
+            assert!(ba.set_returning_previous(3));
+
+            ba.set_range(60, 150);
+            assert!((60..150).all(|b| ba.get(b)));
+        }
+    }
+
+    #[test]
+    fn test_msb_first_word_layout() {
+        let mut lsb = BitArray::new_with_order(100, BitOrder::LsbFirst);
+        let mut msb = BitArray::new_with_order(100, BitOrder::MsbFirst);
+        lsb.set(0);
+        msb.set(0);
+        lsb.set(65);
+        msb.set(65);
+
+        assert!(lsb.as_words() == &[1, 1 << 1][..]);
+        assert!(msb.as_words() == &[1 << 63, 1 << 62][..]);
+        assert!(lsb != msb);
+    }
+
+    #[test]
+    fn test_msb_first_packed_bytes() {
+        let mut lsb = BitArray::new_with_order(20, BitOrder::LsbFirst);
+        let mut msb = BitArray::new_with_order(20, BitOrder::MsbFirst);
+        for &bit in &[0, 9, 17, 19] {
+            lsb.set(bit);
+            msb.set(bit);
+        }
+
+        // The backing words differ, but the packed bytes don't.
+        assert!(lsb.as_words() != msb.as_words());
+        assert!(lsb.to_packed_le_bytes() == vec![0x01, 0x02, 0x0A]);
+        assert!(msb.to_packed_le_bytes() == lsb.to_packed_le_bytes());
+        assert!(BitArray::from_packed_le_bytes(20, &msb.to_packed_le_bytes()) == lsb);
+    }
 }
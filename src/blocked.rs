@@ -7,17 +7,32 @@
 //! a number of Standard Bloom Filters that able to more-easily fit
 //! into the machine cache.
 
+use bit_array::BitArray;
 use hash_until::hash_until;
 use index_mask::index_mask;
 use rand::Rng;
 use rand;
+use splitmix::splitmix64;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 use standard::StandardBloom;
+use std::any::Any;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
+#[cfg(feature = "cache")]
+use std::sync::atomic::{AtomicU64, Ordering};
 use std;
 
 pub use bloom::BloomFilter;
 
+/// The number of bits in a 64 byte cache line.
+const CACHE_LINE_BITS: usize = 512;
+
+/// The number of slots in the cache of `check` results.
+#[cfg(feature = "cache")]
+const RESULT_CACHE_SLOTS: usize = 256;
+
 /// A representation of a BlockedBloom filter.
 ///
 /// ```
@@ -25,7 +40,7 @@ pub use bloom::BloomFilter;
 ///
 /// let expected_set_size = 1024 * 1024;
 /// let bits_per_item = 16;
-/// let hashing_algos = (bits_per_item as f32 * 0.7).ceil() as usize;
+/// let hashing_algos = (bits_per_item as f64 * 0.7).ceil() as usize;
 /// let block_count = 8;
 ///
 /// let mut dbb: DefaultBlockedBloom<usize> = BlockedBloom::new(
@@ -38,10 +53,17 @@ pub use bloom::BloomFilter;
 /// dbb.mark(&100);
 /// assert!(dbb.check(&100));
 /// ```
-pub struct BlockedBloom<H, T> {
-    /// The blocks in this blocked bloom filter are just StandardBloom
-    /// filters.
-    blocks: Vec<Option<Box<StandardBloom<H, T>>>>,
+pub struct BlockedBloom<H, T, B = StandardBloom<H, T>> {
+    /// The blocks in this blocked bloom filter. Unless a different
+    /// factory is given, these are just StandardBloom filters.
+    blocks: Vec<Option<Box<B>>>,
+
+    /// One bit for each block, set once the block is allocated.
+    allocated: BitArray,
+
+    /// The function used to create a block the first time an item is
+    /// marked in it.
+    factory: BlockFactory<B>,
 
     /// The block-selection hasher seed to use.
     hasher_seed: u64,
@@ -54,6 +76,11 @@ pub struct BlockedBloom<H, T> {
     /// The RNG used to generate differnet seeds.
     rng: rand::ThreadRng,
 
+    /// When set, the seeds of each block are derived from this seed
+    /// and the block's index with `seed_for_block` rather than drawn
+    /// from the RNG.
+    master_seed: Option<u64>,
+
     /// The estimated set size.
     n: usize,
 
@@ -64,28 +91,171 @@ pub struct BlockedBloom<H, T> {
     k: usize,
 
     /// The number of N used for each block.
-    n_per_block: usize,
+    n_per_block: Vec<usize>,
+
+    /// True when the bits of each block are rounded up to a power of
+    /// two, as they are by `new_cache_aligned`.
+    pow2_blocks: bool,
+
+    /// An optional small filter covering every item in the blocked
+    /// filter. Checking it first lets most non-members be rejected
+    /// without touching a block.
+    summary: Option<StandardBloom<H, T>>,
+
+    /// Recent items that `check` found, so a repeated check can skip
+    /// hashing the item into its block.
+    #[cfg(feature = "cache")]
+    cache: ResultCache,
 }
 
-impl<H, T> fmt::Debug for BlockedBloom<H, T> {
+/// A direct-mapped cache of the items `check` has found, keyed by the
+/// item's block-selection hash. Only members are cached: bits are
+/// never unset by `mark`, so a cached result stays true until the
+/// filter is cleared. Two items whose hashes are equal share a slot,
+/// which at worst adds a false positive. The slots are atomic so that
+/// `check` can stay `&self`.
+#[cfg(feature = "cache")]
+struct ResultCache {
+    /// Each slot holds a key with its low bit set, or zero when empty.
+    slots: Vec<AtomicU64>,
+}
+
+#[cfg(feature = "cache")]
+impl ResultCache {
+    fn new() -> ResultCache {
+        ResultCache {
+            slots: (0..RESULT_CACHE_SLOTS).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    fn slot(&self, key: u64) -> &AtomicU64 {
+        &self.slots[(key >> 1) as usize % RESULT_CACHE_SLOTS]
+    }
+
+    fn contains(&self, key: u64) -> bool {
+        self.slot(key).load(Ordering::Relaxed) == key | 1
+    }
+
+    fn insert(&self, key: u64) {
+        self.slot(key).store(key | 1, Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        for slot in &self.slots {
+            slot.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(feature = "cache")]
+impl Clone for ResultCache {
+    fn clone(&self) -> ResultCache {
+        ResultCache {
+            slots: self
+                .slots
+                .iter()
+                .map(|s| AtomicU64::new(s.load(Ordering::Relaxed)))
+                .collect(),
+        }
+    }
+}
+
+/// A summary of how full the blocks of a BlockedBloom filter are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FillSummary {
+    /// The fill ratio of the emptiest block.
+    pub min: f64,
+
+    /// The fill ratio of the fullest block.
+    pub max: f64,
+
+    /// The average fill ratio across all blocks.
+    pub mean: f64,
+
+    /// The number of blocks that have been allocated.
+    pub allocated: usize,
+}
+
+/// The natural log of `j!`. Small values are summed exactly, and larger
+/// ones use Stirling's series for `ln(Γ(j + 1))`, which is accurate to
+/// well below `1e-10` from there on.
+fn ln_factorial(j: u64) -> f64 {
+    if j < 20 {
+        return (2..j + 1).map(|i| (i as f64).ln()).sum();
+    }
+
+    let x = j as f64 + 1.0;
+    (x - 0.5) * x.ln() - x + 0.5 * (2.0 * std::f64::consts::PI).ln() + 1.0 / (12.0 * x) -
+        1.0 / (360.0 * x.powi(3)) + 1.0 / (1260.0 * x.powi(5))
+}
+
+/// A function that creates a block for a BlockedBloom filter. It's
+/// given the estimated set size of the block, the number of bits per
+/// member, the number of hashing functions, and two random seeds.
+pub type BlockFactory<B> = fn(usize, usize, usize, u64, u64) -> B;
+
+impl<H, T, B: Clone> Clone for BlockedBloom<H, T, B> {
+    fn clone(&self) -> Self {
+        BlockedBloom {
+            blocks: self.blocks.clone(),
+            allocated: self.allocated.clone(),
+            factory: self.factory,
+            hasher_seed: self.hasher_seed,
+            mask: self.mask,
+            rng: self.rng.clone(),
+            master_seed: self.master_seed,
+            n: self.n,
+            c: self.c,
+            k: self.k,
+            n_per_block: self.n_per_block.clone(),
+            pow2_blocks: self.pow2_blocks,
+            summary: self.summary.clone(),
+            #[cfg(feature = "cache")]
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<H, T, B: fmt::Debug> fmt::Debug for BlockedBloom<H, T, B> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "BlockedBloom {{ blocks: {:?} }}", self.blocks)
     }
 }
 
-impl<H: Hasher + Default, T: Hash> BloomFilter<T> for BlockedBloom<H, T> {
+/// A one-line description of the filter's parameters and how full its
+/// blocks are on average. Use `Debug` to see the blocks themselves.
+impl<H: Hasher + Default, T: Hash> fmt::Display for BlockedBloom<H, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "BlockedBloom(n={}, c={}, k={}, blocks={}, fill={:.1}%)",
+            self.n,
+            self.c,
+            self.k,
+            self.blocks.len(),
+            self.fill_summary().mean * 100.0
+        )
+    }
+}
+
+impl<H, T, B> BloomFilter<T> for BlockedBloom<H, T, B>
+where
+    H: Hasher + Default,
+    T: Hash,
+    B: BloomFilter<T> + Clone,
+{
     fn name(&self) -> &str {
         "blocked"
     }
 
     fn mark(&mut self, item: &T) {
-        let idx = self.block_idx(item);
-
-        if self.blocks[idx].is_none() {
-            let new_block = create_block(self.n_per_block, self.c, self.k, &mut self.rng);
-            self.blocks[idx] = Some(new_block);
+        if let Some(ref mut s) = self.summary {
+            s.mark(item);
         }
 
+        let idx = self.block_idx(item);
+        self.allocate_block(idx);
+
         match self.blocks[idx].as_mut() {
             Some(b) => b.mark(item),
             None => panic!("This should never happen."),
@@ -93,14 +263,68 @@ impl<H: Hasher + Default, T: Hash> BloomFilter<T> for BlockedBloom<H, T> {
     }
 
     fn check(&self, item: &T) -> bool {
-        let idx = self.block_idx(item);
+        // The item's initial hash doubles as its key in the cache, so
+        // it's only hashed once whether or not the cache has it.
+        let (h, initial) = self.block_hasher(item);
+        #[cfg(feature = "cache")]
+        {
+            if self.cache.contains(initial) {
+                return true;
+            }
+        }
+
+        let found = self.check_hashed(item, h, initial);
+
+        #[cfg(feature = "cache")]
+        {
+            if found {
+                self.cache.insert(initial);
+            }
+        }
+
+        found
+    }
+
+    fn clear(&mut self) {
+        #[cfg(feature = "cache")]
+        self.cache.clear();
+
+        if let Some(ref mut s) = self.summary {
+            s.clear();
+        }
 
-        match &self.blocks[idx] {
-            &Some(ref b) => b.check(item),
-            &None => false,
+        for b in self.blocks.iter_mut().flatten() {
+            b.clear();
         }
     }
 
+    /// This doesn't consult or fill the cache of results that `check`
+    /// uses with the `cache` feature: visiting the blocks in order
+    /// already keeps each one hot, and a cache lookup per item would
+    /// cost more than it saves.
+    fn bulk_check(&self, items: &[T]) -> Vec<bool> {
+        let mut results = vec![false; items.len()];
+
+        // Pair each item with its block, and then visit the items
+        // block by block so each block is only brought into the cache
+        // once.
+        let mut by_block: Vec<(usize, usize)> = items
+            .iter()
+            .enumerate()
+            .filter(|&(_, item)| self.summary.as_ref().map_or(true, |s| s.check(item)))
+            .map(|(i, item)| (self.block_idx(item), i))
+            .collect();
+        by_block.sort_unstable();
+
+        for (idx, i) in by_block {
+            if let Some(ref b) = self.blocks[idx] {
+                results[i] = b.check(&items[i]);
+            }
+        }
+
+        results
+    }
+
     fn set_size(&self) -> usize {
         self.n
     }
@@ -112,6 +336,38 @@ impl<H: Hasher + Default, T: Hash> BloomFilter<T> for BlockedBloom<H, T> {
     fn hash_count(&self) -> usize {
         self.k
     }
+
+    fn memory_bytes(&self) -> usize {
+        let blocks: usize = self.blocks
+            .iter()
+            .filter_map(|b| b.as_ref())
+            .map(|b| b.memory_bytes())
+            .sum();
+        let summary = self.summary.as_ref().map_or(0, |s| s.memory_bytes());
+
+        blocks + summary
+    }
+
+    fn as_any(&self) -> &dyn Any
+    where
+        Self: 'static,
+    {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any
+    where
+        Self: 'static,
+    {
+        self
+    }
+
+    fn box_clone(&self) -> Box<dyn BloomFilter<T>>
+    where
+        Self: 'static,
+    {
+        Box::new(self.clone())
+    }
 }
 
 impl<H: Hasher + Default, T: Hash> BlockedBloom<H, T> {
@@ -128,6 +384,12 @@ impl<H: Hasher + Default, T: Hash> BlockedBloom<H, T> {
     /// scaled together to effect the false-positive rate for the
     /// whole system.
     ///
+    /// If `b` is larger than `n`, each block is sized for a single
+    /// member. Block indices are found by masking a 64 bit hash, so any
+    /// number of blocks can be addressed fairly; the practical limit on
+    /// `b` is memory, since a slot for every block is allocated up
+    /// front.
+    ///
     /// The false positive rate can be generalized as follows:
     ///
     /// ```
@@ -145,47 +407,354 @@ impl<H: Hasher + Default, T: Hash> BlockedBloom<H, T> {
         assert!(k > 0);
         assert!(b > 0);
 
-        assert!(k <= c);
-
         // Ideally, N insertions divide evenly into B. The number of
-        // bits we use for each B should be (N/B * C).
+        // bits we use for each B should be (N/B * C). This is integer
+        // division rounding up, since floats lose precision for large N.
+        let n_per_block = n.div_ceil(b);
 
-        let max_block_index = b - 1;
+        BlockedBloom::new_from_parts(n, c, k, vec![n_per_block; b], StandardBloom::new_with_seeds)
+    }
+
+    /// Like `new`, but each block is exactly one 64 byte cache line, as
+    /// in the design from Kaler's paper. Each block holds as many
+    /// members as fit in 512 bits at `c` bits per member, and enough
+    /// blocks are used to hold `n` members. Any bits left over after
+    /// `n_per_block * c` are still used, which slightly lowers the
+    /// false positive rate.
+    pub fn new_cache_aligned(n: usize, c: usize, k: usize) -> Self {
+        assert!(n > 0);
+        assert!(c > 0);
+        assert!(c <= CACHE_LINE_BITS);
+
+        let n_per_block = CACHE_LINE_BITS / c;
+        let b = n.div_ceil(n_per_block);
+
+        // Since n_per_block * c is always more than half a cache line,
+        // rounding it up to a power of two gives exactly one line.
+        let mut bb = BlockedBloom::new_from_parts(
+            n,
+            c,
+            k,
+            vec![n_per_block; b],
+            StandardBloom::new_pow2_with_seeds,
+        );
+        bb.pow2_blocks = true;
+        bb
+    }
+
+    /// Like `new`, but also maintains a summary filter in front of the
+    /// blocks using `summary_c` bits per member. Every item marked in
+    /// the filter is also marked in the summary, and `check` consults
+    /// the summary first to quickly reject non-members.
+    pub fn new_with_summary(n: usize, c: usize, k: usize, b: usize, summary_c: usize) -> Self {
+        let mut bb = BlockedBloom::new(n, c, k, b);
+        bb.summary = Some(StandardBloom::new_auto_k(n, summary_c));
+        bb
+    }
+
+    /// The fill ratio of each block. Blocks that haven't been
+    /// allocated yet have a fill ratio of 0.0.
+    pub fn block_fill_ratios(&self) -> Vec<f64> {
+        self.blocks
+            .iter()
+            .map(|b| b.as_ref().map_or(0.0, |b| b.fill_ratio()))
+            .collect()
+    }
+
+    /// Summarize the fill ratios of the blocks.
+    pub fn fill_summary(&self) -> FillSummary {
+        let ratios = self.block_fill_ratios();
+
+        FillSummary {
+            min: ratios.iter().cloned().fold(f64::INFINITY, f64::min),
+            max: ratios.iter().cloned().fold(0.0, f64::max),
+            mean: ratios.iter().sum::<f64>() / ratios.len() as f64,
+            allocated: self.blocks.iter().filter(|b| b.is_some()).count(),
+        }
+    }
+
+    /// Like `new`, but every seed used by the filter is derived from
+    /// `master_seed`. The seeds of any one block can be recovered with
+    /// `seed_for_block(master_seed, index)` without building the rest
+    /// of the filter.
+    pub fn new_with_master_seed(n: usize, c: usize, k: usize, b: usize, master_seed: u64) -> Self {
+        let mut bb = BlockedBloom::new(n, c, k, b);
+        bb.hasher_seed = splitmix64(master_seed);
+        bb.master_seed = Some(master_seed);
+        bb
+    }
+
+    /// Calculate the probability of a false positive, accounting for
+    /// blocks that receive more than their share of the items.
+    ///
+    /// `false_positive_probability` assumes every block receives
+    /// exactly `n/b` items, but items are assigned to blocks at random,
+    /// so the number of items landing in a block follows a Poisson
+    /// distribution instead. Overfull blocks have a much higher false
+    /// positive rate than underfull blocks make up for, so the real
+    /// rate is higher than the standard formula predicts. This averages
+    /// the false positive rate of each block over that distribution, as
+    /// described in the Kaler paper.
+    pub fn false_positive_probability_blocked(&self) -> f64 {
+        let b = self.blocks.len() as f64;
+        let lambda = self.n as f64 / b;
+        let k = self.k as f64;
+
+        // The number of items in a block is almost never further than
+        // this from the mean.
+        let spread = 10.0 * lambda.sqrt() + 10.0;
+        let lo = (lambda - spread).max(0.0) as u64;
+        let hi = (lambda + spread).ceil() as u64;
+
+        // Blocks of the same size have the same false positive rate.
+        let mut sizes: BTreeMap<usize, usize> = BTreeMap::new();
+        for n_block in &self.n_per_block {
+            *sizes.entry(*n_block).or_insert(0) += 1;
+        }
+
+        let mut total = 0.0;
+        for (n_block, count) in sizes {
+            let mut m = n_block * self.c;
+            if self.pow2_blocks {
+                m = m.next_power_of_two();
+            }
+            let m = m as f64;
+
+            // ln(j!) is accumulated across the window rather than
+            // recomputed each time, and the Poisson probabilities are
+            // calculated as logs so that large means don't underflow.
+            let mut ln_fact = ln_factorial(lo.saturating_sub(1));
+            let mut fp = 0.0;
+            for j in lo..hi + 1 {
+                if j > 0 {
+                    ln_fact += (j as f64).ln();
+                }
+
+                let ln_p = -lambda + j as f64 * lambda.ln() - ln_fact;
+                let fp_j = (1.0 - (-k * j as f64 / m).exp()).powf(k);
+                fp += ln_p.exp() * fp_j;
+            }
+
+            total += fp * count as f64;
+        }
+
+        total / b
+    }
+
+    /// Create a new blocked bloom filter where each block is sized
+    /// independently. There is one block for each entry in
+    /// `per_block_n`, and each entry is the estimated number of items
+    /// in that block. `c` and `k` are the same as in `new`.
+    pub fn new_with_block_sizes(per_block_n: &[usize], c: usize, k: usize) -> Self {
+        assert!(!per_block_n.is_empty());
+        assert!(per_block_n.iter().all(|n| *n > 0));
+
+        let n = per_block_n.iter().sum();
+        BlockedBloom::new_from_parts(n, c, k, per_block_n.to_vec(), StandardBloom::new_with_seeds)
+    }
+}
+
+impl<H, T, B> BlockedBloom<H, T, B>
+where
+    H: Hasher + Default,
+    T: Hash,
+    B: BloomFilter<T>,
+{
+    /// Like `new`, but each block is created with `factory` rather
+    /// than being a StandardBloom filter. This allows any BloomFilter
+    /// to be used for the blocks.
+    pub fn new_with_factory(
+        n: usize,
+        c: usize,
+        k: usize,
+        b: usize,
+        factory: BlockFactory<B>,
+    ) -> Self {
+        assert!(n > 0);
+        assert!(b > 0);
+
+        let n_per_block = n.div_ceil(b);
+
+        BlockedBloom::new_from_parts(n, c, k, vec![n_per_block; b], factory)
+    }
+
+    fn new_from_parts(
+        n: usize,
+        c: usize,
+        k: usize,
+        n_per_block: Vec<usize>,
+        factory: BlockFactory<B>,
+    ) -> Self {
+        assert!(c > 0);
+        assert!(k > 0);
+
+        assert!(k <= c);
+
+        let max_block_index = n_per_block.len() - 1;
 
         let mut rng = rand::thread_rng();
 
         BlockedBloom {
-            n: n,
-            c: c,
-            k: k,
-
-            n_per_block: (n as f32 / b as f32).ceil() as usize,
+            n,
+            c,
+            k,
 
             hasher_seed: rng.gen::<u64>(),
             mask: index_mask(max_block_index as u64),
 
-            rng: rng,
+            rng,
+            master_seed: None,
+
+            blocks: n_per_block.iter().map(|_| None).collect(),
+            allocated: BitArray::new(n_per_block.len()),
+            factory,
+
+            n_per_block,
+            pow2_blocks: false,
 
-            blocks: (0..b).map(|_| None).collect(),
+            summary: None,
+            #[cfg(feature = "cache")]
+            cache: ResultCache::new(),
         }
     }
 
-    /// Determine a block index from an item. The block index for a
-    /// given item will always be the same.
-    fn block_idx(&self, item: &T) -> usize {
-        // We create a hash for the item by calculating hashes for the
-        // item until one of those hashes is usable as a block index
-        // after masking off the top bits.
+    /// Build a single StandardBloom filter with `c` bits per member
+    /// and `k` hashing functions, sized for the same estimated set
+    /// size as this filter. The bits of the blocks can't be copied into
+    /// a standard filter since they're laid out differently, so every
+    /// member must be provided again by `items`.
+    pub fn to_standard<I>(&self, items: I, c: usize, k: usize) -> StandardBloom<H, T>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut standard = StandardBloom::new(self.n, c, k);
+        for item in items {
+            standard.mark(&item);
+        }
+        standard
+    }
+
+    /// A bit for each block, set when the block has been allocated.
+    /// Blocks are allocated the first time an item is marked in them,
+    /// so a clear bit means no item in that block is a member.
+    pub fn allocated_bitmap(&self) -> &BitArray {
+        &self.allocated
+    }
+
+    /// The mask applied to each hash before it's checked against the
+    /// number of blocks. It always covers every block index.
+    pub fn block_mask(&self) -> u64 {
+        self.mask
+    }
+
+    /// Check the filter's internal invariants, returning a description
+    /// of the first one that doesn't hold. A filter built by this crate
+    /// always validates; this is meant for catching corruption. The
+    /// blocks themselves can be any BloomFilter, so only the summary
+    /// filter is validated along with the blocked filter.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.k == 0 {
+            return Err("k is zero");
+        }
+        if self.blocks.is_empty() {
+            return Err("there are no blocks");
+        }
+        if self.n_per_block.len() != self.blocks.len() {
+            return Err("the block sizes don't match the number of blocks");
+        }
+        if self.n_per_block.iter().sum::<usize>() < self.n {
+            return Err("the blocks are sized for fewer than n members");
+        }
+        if (self.blocks.len() - 1) as u64 & !self.mask != 0 {
+            return Err("the mask doesn't cover every block");
+        }
+        if self.allocated.width() != self.blocks.len() {
+            return Err("the allocated bitmap doesn't match the number of blocks");
+        }
+        if self.blocks.iter().enumerate().any(|(i, b)| self.allocated.get(i) != b.is_some()) {
+            return Err("the allocated bitmap doesn't match the blocks");
+        }
+        match self.summary {
+            Some(ref s) => s.validate(),
+            None => Ok(()),
+        }
+    }
+
+    /// Count how many of the sample `items` map to each block. This
+    /// does not mark anything in the filter, but can be used to detect
+    /// a skewed distribution of items across the blocks.
+    pub fn block_load(&self, items: &[T]) -> Vec<usize> {
+        let mut load = vec![0; self.blocks.len()];
+
+        for item in items {
+            load[self.block_idx(item)] += 1;
+        }
+
+        load
+    }
+
+    /// Create the block at `idx` if it doesn't exist yet.
+    fn allocate_block(&mut self, idx: usize) {
+        if self.blocks[idx].is_none() {
+            let (seed1, seed2) = match self.master_seed {
+                Some(master) => seed_for_block(master, idx),
+                None => (self.rng.gen::<u64>(), self.rng.gen::<u64>()),
+            };
+
+            let new_block = (self.factory)(self.n_per_block[idx], self.c, self.k, seed1, seed2);
+            self.blocks[idx] = Some(Box::new(new_block));
+            self.allocated.set(idx);
+        }
+    }
+
+    /// `check` without consulting the cache of results.
+    #[cfg(all(test, feature = "cache"))]
+    fn check_uncached(&self, item: &T) -> bool {
+        let (h, initial) = self.block_hasher(item);
+        self.check_hashed(item, h, initial)
+    }
+
+    /// `check` without consulting the cache of results, for an item
+    /// that `block_hasher` has already hashed to `h` and `initial`.
+    fn check_hashed(&self, item: &T, h: H, initial: u64) -> bool {
+        if let Some(ref s) = self.summary {
+            if !s.check(item) {
+                return false;
+            }
+        }
 
-        // A hasher with the block-picking seed.
+        let idx = self.block_idx_hashed(h, initial);
+
+        match self.blocks[idx] {
+            Some(ref b) => b.check(item),
+            None => false,
+        }
+    }
+
+    /// A hasher with the block-picking seed that has hashed `item`,
+    /// along with the initial hash of the item.
+    fn block_hasher(&self, item: &T) -> (H, u64) {
         let mut h: H = Default::default();
         h.write_u64(self.hasher_seed);
-
-        // Incorporate the item value into the hash.
         item.hash(&mut h);
 
-        // The initial hash of the item.
         let initial = h.finish();
+        (h, initial)
+    }
+
+    /// Determine a block index from an item. The block index for a
+    /// given item will always be the same.
+    fn block_idx(&self, item: &T) -> usize {
+        let (h, initial) = self.block_hasher(item);
+        self.block_idx_hashed(h, initial)
+    }
+
+    /// `block_idx` for an item that `block_hasher` has already hashed
+    /// to `h` and `initial`.
+    fn block_idx_hashed(&self, mut h: H, initial: u64) -> usize {
+        // We create a hash for the item by calculating hashes for the
+        // item until one of those hashes is usable as a block index
+        // after masking off the top bits.
 
         // A property to test that a given hash is able to represent a
         // block index.
@@ -199,31 +768,72 @@ impl<H: Hasher + Default, T: Hash> BlockedBloom<H, T> {
     }
 }
 
-/// A BlockedBloom filter that uses the DefaultHasher.
-pub type DefaultBlockedBloom<T> = BlockedBloom<std::collections::hash_map::DefaultHasher, T>;
-
-fn create_block<H, T>(
-    n_per_block: usize,
-    c: usize,
-    k: usize,
-    rng: &mut rand::ThreadRng,
-) -> Box<StandardBloom<H, T>>
+#[cfg(feature = "rayon")]
+impl<H, T, B> BlockedBloom<H, T, B>
 where
     H: Hasher + Default,
-    T: Hash,
+    T: Hash + Sync,
+    B: BloomFilter<T> + Send,
 {
-    Box::new(StandardBloom::new_with_seeds(
-        n_per_block,
-        c,
-        k,
-        rng.gen::<u64>(),
-        rng.gen::<u64>(),
-    ))
+    /// Mark every item in `items`, inserting into the blocks in
+    /// parallel. Items are first grouped by their block, and then each
+    /// block is given to exactly one task, so no two tasks ever share a
+    /// block. The result is the same as marking the items one at a time.
+    pub fn mark_all_parallel(&mut self, items: &[T]) {
+        let mut per_block: Vec<Vec<&T>> = self.blocks.iter().map(|_| Vec::new()).collect();
+
+        for item in items {
+            if let Some(ref mut s) = self.summary {
+                s.mark(item);
+            }
+
+            per_block[self.block_idx(item)].push(item);
+        }
+
+        // Blocks are created up front since creating one draws seeds
+        // from the shared RNG.
+        for (idx, block_items) in per_block.iter().enumerate() {
+            if !block_items.is_empty() {
+                self.allocate_block(idx);
+            }
+        }
+
+        self.blocks
+            .par_iter_mut()
+            .zip(per_block.into_par_iter())
+            .for_each(|(block, block_items)| if let Some(ref mut b) = *block {
+                for item in block_items {
+                    b.mark(item);
+                }
+            });
+    }
 }
 
+/// Derive the two seeds of the block at `block_index` in a filter
+/// created with `BlockedBloom::new_with_master_seed`. The same
+/// `master_seed` and `block_index` always give the same seeds.
+pub fn seed_for_block(master_seed: u64, block_index: usize) -> (u64, u64) {
+    let i = block_index as u64;
+    (
+        splitmix64(master_seed ^ splitmix64(2 * i)),
+        splitmix64(master_seed ^ splitmix64(2 * i + 1)),
+    )
+}
+
+/// A BlockedBloom filter that uses the DefaultHasher.
+pub type DefaultBlockedBloom<T> = BlockedBloom<std::collections::hash_map::DefaultHasher, T>;
+
+/// A BlockedBloom filter that uses aHash, which is faster than the
+/// DefaultHasher. aHash doesn't promise the same hashes across
+/// versions or machines, so a serialized filter may not be usable
+/// anywhere but where it was created.
+#[cfg(feature = "ahash")]
+pub type AHashBlockedBloom<T> = BlockedBloom<::ahash::AHasher, T>;
+
 #[cfg(test)]
 mod tests {
-    use bloom::optimal_hashers;
+    use bloom::{false_positive_probability, optimal_hashers};
+    use std::collections::hash_map::DefaultHasher;
     use super::*;
 
     #[test]
@@ -234,4 +844,337 @@ mod tests {
         bb.mark(&100);
         assert!(bb.check(&100));
     }
+
+    #[test]
+    fn block_load_counts_every_item() {
+        let bb: DefaultBlockedBloom<usize> = BlockedBloom::new(1024, 16, optimal_hashers(16), 8);
+        let sample: Vec<usize> = (0..1000).collect();
+        let load = bb.block_load(&sample);
+
+        assert!(load.len() == 8);
+        assert!(load.iter().sum::<usize>() == sample.len());
+        assert!(!bb.check(&0));
+    }
+
+    #[test]
+    fn heterogeneous_block_sizes() {
+        let sizes = [16, 1024, 64, 4096, 8];
+        let mut bb: DefaultBlockedBloom<usize> =
+            BlockedBloom::new_with_block_sizes(&sizes, 16, optimal_hashers(16));
+
+        assert!(bb.set_size() == sizes.iter().sum::<usize>());
+        assert!(bb.block_load(&[1, 2, 3]).len() == sizes.len());
+
+        for i in 0..1000 {
+            bb.mark(&i);
+        }
+
+        for i in 0..1000 {
+            assert!(bb.check(&i));
+        }
+    }
+
+    #[test]
+    fn summary_has_no_false_negatives() {
+        let mut bb: DefaultBlockedBloom<usize> =
+            BlockedBloom::new_with_summary(1024, 16, optimal_hashers(16), 8, 4);
+
+        assert!(!bb.check(&100));
+
+        for i in 0..1024 {
+            bb.mark(&i);
+        }
+
+        for i in 0..1024 {
+            assert!(bb.check(&i));
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_marking_matches_sequential() {
+        let items: Vec<usize> = (0..10000).collect();
+
+        // With the same master seed, both filters pick the same block
+        // for each item and give each block the same seeds.
+        let mut par: DefaultBlockedBloom<usize> =
+            BlockedBloom::new_with_master_seed(10000, 16, optimal_hashers(16), 16, 42);
+        par.mark_all_parallel(&items);
+
+        let mut seq: DefaultBlockedBloom<usize> =
+            BlockedBloom::new_with_master_seed(10000, 16, optimal_hashers(16), 16, 42);
+        for i in &items {
+            seq.mark(i);
+        }
+
+        assert!(items.iter().all(|i| par.check(i)));
+        assert!(par.blocks == seq.blocks);
+        assert!(par.allocated == seq.allocated);
+    }
+
+    #[test]
+    fn blocks_from_a_factory() {
+        // The blocks of this filter are themselves blocked filters.
+        let mut bb: BlockedBloom<DefaultHasher, usize, DefaultBlockedBloom<usize>> =
+            BlockedBloom::new_with_factory(1024, 16, optimal_hashers(16), 4, |n, c, k, _, _| {
+                BlockedBloom::new(n, c, k, 2)
+            });
+
+        assert!(!bb.check(&100));
+
+        for i in 0..1024 {
+            bb.mark(&i);
+        }
+
+        for i in 0..1024 {
+            assert!(bb.check(&i));
+        }
+    }
+
+    #[test]
+    fn cache_aligned_blocks_fill_a_line() {
+        for c in &[1, 10, 16, 24, 100, 300, 512] {
+            let mut bb: DefaultBlockedBloom<usize> =
+                BlockedBloom::new_cache_aligned(1000, *c, 1);
+            assert!(bb.blocks.len() == 1000usize.div_ceil(CACHE_LINE_BITS / c));
+
+            for i in 0..1000 {
+                bb.mark(&i);
+            }
+
+            assert!((0..1000).all(|i| bb.check(&i)));
+            for block in bb.blocks.iter().flatten() {
+                assert!(block.memory_bytes() * 8 == CACHE_LINE_BITS);
+                #[cfg(feature = "stats")]
+                assert!(block.rejection_count() == 0);
+            }
+        }
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn cached_checks_match_uncached_checks() {
+        let mut bb: DefaultBlockedBloom<usize> = BlockedBloom::new(1024, 16, 8, 4);
+        for i in 0..512 {
+            bb.mark(&i);
+        }
+
+        for _ in 0..2 {
+            assert!((0..2048).all(|i| bb.check(&i) == bb.check_uncached(&i)));
+        }
+        assert!(bb.check(&7));
+        assert!(bb.cache.contains(bb.block_hasher(&7).1));
+
+        // Misses aren't cached, so marking an item makes it visible.
+        assert!(!bb.check(&5000));
+        bb.mark(&5000);
+        assert!(bb.check(&5000));
+
+        bb.clear();
+        assert!(!bb.check(&7));
+        assert!(!bb.check(&5000));
+    }
+
+    #[test]
+    fn clear_empties_every_block() {
+        let mut bb: DefaultBlockedBloom<usize> = BlockedBloom::new_with_summary(1024, 16, 8, 4, 4);
+        for i in 0..1024 {
+            bb.mark(&i);
+        }
+
+        bb.clear();
+        assert!(bb.blocks.len() == 4);
+        assert!(bb.fill_summary().max == 0.0);
+        assert!((0..1024).all(|i| !bb.check(&i)));
+    }
+
+    #[test]
+    fn allocated_bitmap_matches_blocks() {
+        let mut bb: DefaultBlockedBloom<usize> = BlockedBloom::new(1024, 16, 8, 64);
+        assert!(bb.allocated_bitmap().count_ones() == 0);
+
+        for i in 0..20 {
+            bb.mark(&i);
+        }
+
+        let bitmap = bb.allocated_bitmap();
+        assert!(bitmap.width() == 64);
+        assert!(bitmap.count_ones() > 0);
+        for (idx, block) in bb.blocks.iter().enumerate() {
+            assert!(bitmap.get(idx) == block.is_some());
+        }
+    }
+
+    #[test]
+    fn corrupted_filter_fails_validation() {
+        let mut bb: DefaultBlockedBloom<usize> = BlockedBloom::new_with_summary(1024, 16, 8, 5, 4);
+        for i in 0..20 {
+            bb.mark(&i);
+        }
+        assert!(bb.validate() == Ok(()));
+
+        let mut corrupt = bb.clone();
+        corrupt.mask = 1;
+        assert!(corrupt.validate() == Err("the mask doesn't cover every block"));
+
+        let mut corrupt = bb.clone();
+        corrupt.allocated.clear_all();
+        assert!(corrupt.validate().is_err());
+    }
+
+    #[test]
+    fn block_mask_covers_the_blocks() {
+        for b in &[1, 3, 4, 5, 100] {
+            let bb: DefaultBlockedBloom<usize> = BlockedBloom::new(1000, 10, 7, *b);
+            assert!(bb.block_mask() >= *b as u64 - 1);
+            assert!((bb.block_mask() + 1).is_power_of_two());
+        }
+    }
+
+    #[test]
+    fn large_n_is_divided_exactly() {
+        // f32 can't represent these values of n exactly, and would give
+        // one less member per block than needed.
+        for &(n, b) in &[(16_777_217, 2), (123_456_789, 7), (200_000_003, 1000)] {
+            let exact = n / b + if n % b == 0 { 0 } else { 1 };
+            let bb: DefaultBlockedBloom<usize> = BlockedBloom::new(n, 1, 1, b);
+            assert!(bb.n_per_block.iter().all(|per| *per == exact));
+            assert!(bb.n_per_block.iter().sum::<usize>() >= n);
+            assert!((n as f32 / b as f32).ceil() as usize != exact);
+        }
+    }
+
+    #[test]
+    fn millions_of_blocks_are_addressable() {
+        let b = 3_000_017;
+        let bb: DefaultBlockedBloom<usize> = BlockedBloom::new(b, 1, 1, b);
+
+        let mut max_idx = 0;
+        for i in 0..1_000_000 {
+            let idx = bb.block_idx(&i);
+            assert!(idx < b);
+            max_idx = max_idx.max(idx);
+        }
+
+        assert!(max_idx > b - b / 100);
+    }
+
+    #[test]
+    fn more_blocks_than_members_still_works() {
+        let mut bb: DefaultBlockedBloom<usize> = BlockedBloom::new(10, 16, 8, 11);
+        for i in 0..10 {
+            bb.mark(&i);
+        }
+        assert!((0..10).all(|i| bb.check(&i)));
+    }
+
+    #[test]
+    fn display_is_compact() {
+        let mut bb: DefaultBlockedBloom<usize> = BlockedBloom::new(1024, 16, 11, 4);
+        assert!(bb.to_string() == "BlockedBloom(n=1024, c=16, k=11, blocks=4, fill=0.0%)");
+
+        bb.mark(&100);
+        let s = bb.to_string();
+        assert!(s.contains("n=1024") && s.contains("blocks=4"));
+        assert!(s.contains('%'));
+    }
+
+    #[test]
+    fn fill_summary_of_skewed_blocks() {
+        let mut bb: DefaultBlockedBloom<usize> =
+            BlockedBloom::new_with_block_sizes(&[8, 1024, 1024, 1024], 16, optimal_hashers(16));
+
+        for i in 0..200 {
+            bb.mark(&i);
+        }
+
+        let summary = bb.fill_summary();
+        assert!(summary.allocated == 4);
+        assert!(summary.max > summary.mean);
+        assert!(summary.mean > summary.min);
+        assert!(summary.min > 0.0);
+    }
+
+    #[cfg(feature = "ahash")]
+    #[test]
+    fn ahash_filter_works() {
+        let mut bb: AHashBlockedBloom<usize> =
+            BlockedBloom::new(1024, 16, optimal_hashers(16), 4);
+        assert!(!bb.check(&100));
+        bb.mark(&100);
+        assert!(bb.check(&100));
+    }
+
+    #[test]
+    fn block_seeds_from_master_seed() {
+        assert!(seed_for_block(42, 3) == seed_for_block(42, 3));
+        assert!(seed_for_block(42, 3) != seed_for_block(42, 4));
+        assert!(seed_for_block(42, 3) != seed_for_block(43, 3));
+
+        let mut a: DefaultBlockedBloom<usize> =
+            BlockedBloom::new_with_master_seed(1024, 16, optimal_hashers(16), 8, 42);
+        let mut b: DefaultBlockedBloom<usize> =
+            BlockedBloom::new_with_master_seed(1024, 16, optimal_hashers(16), 8, 42);
+
+        for i in 0..100 {
+            a.mark(&i);
+            b.mark(&i);
+        }
+
+        for (x, y) in a.blocks.iter().zip(&b.blocks) {
+            assert!(x == y);
+        }
+    }
+
+    #[test]
+    fn convert_to_standard() {
+        let mut bb: DefaultBlockedBloom<usize> =
+            BlockedBloom::new(1024, 16, optimal_hashers(16), 8);
+        for i in 0..1024 {
+            bb.mark(&i);
+        }
+
+        let standard = bb.to_standard(0..1024, 16, optimal_hashers(16));
+        assert!(standard.set_size() == bb.set_size());
+        for i in 0..1024 {
+            assert!(standard.check(&i));
+        }
+    }
+
+    #[test]
+    fn ln_factorial_matches_the_exact_sum() {
+        for j in 0..2000u64 {
+            let exact: f64 = (2..j + 1).map(|i| (i as f64).ln()).sum();
+            assert!((ln_factorial(j) - exact).abs() <= 1e-9 * exact.max(1.0));
+        }
+    }
+
+    #[test]
+    fn blocked_fp_estimate_uses_the_block_width() {
+        let bb: DefaultBlockedBloom<usize> = BlockedBloom::new_cache_aligned(10000, 7, 5);
+
+        // Each block holds 73 members in a full 512 bit cache line, not
+        // the 511 bits `n_per_block * c` would give.
+        let mut unaligned = bb.clone();
+        unaligned.pow2_blocks = false;
+        let aligned_fp = bb.false_positive_probability_blocked();
+        assert!(aligned_fp < unaligned.false_positive_probability_blocked());
+    }
+
+    #[test]
+    fn blocked_fp_estimate_matches_observed() {
+        let n = 10000;
+        let mut bb: DefaultBlockedBloom<usize> = BlockedBloom::new(n, 8, optimal_hashers(8), 64);
+        for i in 0..n {
+            bb.mark(&i);
+        }
+
+        let standard = false_positive_probability(n, 8, optimal_hashers(8));
+        let blocked = bb.false_positive_probability_blocked();
+        assert!(blocked > standard);
+
+        let probes = 100000;
+        let observed = (n..n + probes).filter(|i| bb.check(i)).count() as f64 / probes as f64;
+        assert!((observed - blocked).abs() < blocked * 0.25);
+    }
 }
@@ -1,12 +1,81 @@
 //! A trait defining a bloom filter.
 
+use std::any::Any;
 use std::hash::Hash;
-use std::f32;
 
 /// Get an optimal number of hashing functions to use from a given
 /// number of bits per set member.
 pub fn optimal_hashers(c: usize) -> usize {
-    (c as f32 * 2.0f32.ln()).ceil() as usize
+    // f32 only has about 7 significant digits, which isn't enough to
+    // round large values of c correctly.
+    (c as f64 * 2.0f64.ln()).ceil() as usize
+}
+
+/// How `optimal_hashers_rounded` turns the optimal number of hashing
+/// functions, `c * ln(2)`, into a whole number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundPolicy {
+    /// Round up, as `optimal_hashers` does.
+    Ceil,
+
+    /// Round down.
+    Floor,
+
+    /// Round to the closest whole number, with halves rounded up.
+    Nearest,
+}
+
+/// Like `optimal_hashers`, but rounds `c * ln(2)` with `policy`. The
+/// result is never less than one.
+pub fn optimal_hashers_rounded(c: usize, policy: RoundPolicy) -> usize {
+    let k = c as f64 * 2.0f64.ln();
+    let rounded = match policy {
+        RoundPolicy::Ceil => k.ceil(),
+        RoundPolicy::Floor => k.floor(),
+        RoundPolicy::Nearest => k.round(),
+    };
+
+    (rounded as usize).max(1)
+}
+
+/// Get the number of bits per set member needed to achieve a false
+/// positive probability of `target_fp` when using the optimal number
+/// of hashing functions.
+pub fn optimal_bits_per_member(target_fp: f64) -> usize {
+    assert!(target_fp > 0.0 && target_fp < 1.0);
+
+    (-target_fp.ln() / 2.0f64.ln().powi(2)).ceil() as usize
+}
+
+/// The sizing of a bloom filter planned by `plan`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BloomPlan {
+    /// The total number of bits in the filter.
+    pub total_bits: usize,
+
+    /// The number of bytes needed to store the bits.
+    pub bytes: usize,
+
+    /// The number of bits per member.
+    pub c: usize,
+
+    /// The number of hashing functions.
+    pub k: usize,
+}
+
+/// Plan the size of a bloom filter holding `n` items with a false
+/// positive probability of `target_fp` without allocating anything.
+pub fn plan(n: usize, target_fp: f64) -> BloomPlan {
+    let c = optimal_bits_per_member(target_fp);
+    let total_bits = n * c;
+
+    BloomPlan {
+        total_bits,
+        // Bits are stored in 64-bit words.
+        bytes: total_bits.div_ceil(64) * 8,
+        c,
+        k: optimal_hashers(c),
+    }
 }
 
 /// Calculate the probability of a false positive from the estimated
@@ -20,6 +89,32 @@ pub fn false_positive_probability(n: usize, c: usize, k: usize) -> f64 {
     (1f64 - e.powf((-k * n as f64) / m)).powf(k)
 }
 
+/// The parameters that describe the shape of a bloom filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BloomParams {
+    /// The estimated set size.
+    pub n: usize,
+
+    /// The number of bits per member.
+    pub c: usize,
+
+    /// The number of hashing functions.
+    pub k: usize,
+}
+
+impl BloomParams {
+    /// Calculate the probability of a false positive for a filter
+    /// with these parameters. See `false_positive_probability`.
+    pub fn false_positive_probability(&self) -> f64 {
+        false_positive_probability(self.n, self.c, self.k)
+    }
+}
+
+/// Returned from `BloomFilter::try_mark` when a filter is too full
+/// to accept more items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Saturated;
+
 /// Bloom filters all need to support get and set operations.
 pub trait BloomFilter<T: Hash> {
     /// The implementation name of the bloom filter.
@@ -27,12 +122,83 @@ pub trait BloomFilter<T: Hash> {
         ""
     }
 
+    /// The name of the bloom filter along with its parameters, such as
+    /// `standard[n=1024,c=16,k=12]`, to tell filters apart in logs.
+    fn full_name(&self) -> String {
+        format!(
+            "{}[n={},c={},k={}]",
+            self.name(),
+            self.set_size(),
+            self.bits_per_member(),
+            self.hash_count()
+        )
+    }
+
     /// Set the bits for `item` in the BloomFilter.
     fn mark(&mut self, item: &T);
 
     /// True if the bits for `item` in the BloomFilter are all set.
     fn check(&self, item: &T) -> bool;
 
+    /// The same as `check`, under the name used by `HashSet` and other
+    /// set types. Unlike a `HashSet`, a true result only means `item`
+    /// may be a member.
+    fn contains(&self, item: &T) -> bool {
+        self.check(item)
+    }
+
+    /// The same as `mark`, under the name used by `HashSet` and other
+    /// set types. `mark` and `check` remain the main names, since
+    /// they describe what a bloom filter does with its bits.
+    fn insert(&mut self, item: &T) {
+        self.mark(item)
+    }
+
+    /// Remove every member from the BloomFilter. Its parameters and
+    /// seeds are unchanged, so it behaves like a freshly created filter
+    /// with the same seeds.
+    fn clear(&mut self);
+
+    /// Check every item in `items`, returning the result of `check`
+    /// for each one in the same order.
+    fn bulk_check(&self, items: &[T]) -> Vec<bool> {
+        items.iter().map(|item| self.check(item)).collect()
+    }
+
+    /// Like `mark`, but fails rather than marking `item` when the
+    /// BloomFilter is too full. Filters without a limit always
+    /// succeed.
+    fn try_mark(&mut self, item: &T) -> Result<(), Saturated> {
+        self.mark(item);
+        Ok(())
+    }
+
+    /// Set the bits for `item` in the BloomFilter, returning what
+    /// `check` would have returned for `item` beforehand.
+    fn mark_and_check(&mut self, item: &T) -> bool {
+        let present = self.check(item);
+        self.mark(item);
+        present
+    }
+
+    /// Mark `item`, returning true if it was not already present. This
+    /// makes the BloomFilter usable for skipping items that have been
+    /// seen before. A false positive makes a new item look like it was
+    /// already present, so new items are occasionally skipped, but an
+    /// item that was marked is never reported as new again.
+    fn insert_if_novel(&mut self, item: &T) -> bool {
+        !self.mark_and_check(item)
+    }
+
+    /// Mark every item in `items`, returning the number of items that
+    /// were not already present.
+    fn mark_many_checked(&mut self, items: &[T]) -> usize {
+        items
+            .iter()
+            .filter(|item| self.insert_if_novel(item))
+            .count()
+    }
+
     /// The estimated set size of the BloomFilter.
     fn set_size(&self) -> usize;
 
@@ -41,4 +207,451 @@ pub trait BloomFilter<T: Hash> {
 
     /// The number of hashing functions used.
     fn hash_count(&self) -> usize;
+
+    /// The number of bytes used to store the bits of the BloomFilter.
+    fn memory_bytes(&self) -> usize;
+
+    /// The number of bytes used per member of the estimated set size.
+    fn bytes_per_member(&self) -> f64 {
+        self.memory_bytes() as f64 / self.set_size() as f64
+    }
+
+    /// A one-line description of the BloomFilter, such as
+    /// `standard n=1024 c=16 k=12 fp~0.000466`, for use in logs.
+    fn summary_string(&self) -> String {
+        let p = self.parameters();
+        format!(
+            "{} n={} c={} k={} fp~{:.6}",
+            self.name(),
+            p.n,
+            p.c,
+            p.k,
+            p.false_positive_probability()
+        )
+    }
+
+    /// The BloomFilter as `Any`, allowing a trait object to be
+    /// downcast back to its concrete type.
+    fn as_any(&self) -> &dyn Any
+    where
+        Self: 'static;
+
+    /// Like `as_any`, but mutable.
+    fn as_any_mut(&mut self) -> &mut dyn Any
+    where
+        Self: 'static;
+
+    /// A copy of the BloomFilter in a new box. This allows a boxed
+    /// trait object to be cloned, which `Clone` can't do.
+    fn box_clone(&self) -> Box<dyn BloomFilter<T>>
+    where
+        Self: 'static;
+
+    /// The estimated set size, bits per member, and number of hashing
+    /// functions of the BloomFilter bundled together.
+    fn parameters(&self) -> BloomParams {
+        BloomParams {
+            n: self.set_size(),
+            c: self.bits_per_member(),
+            k: self.hash_count(),
+        }
+    }
+}
+
+/// A mutable reference to a BloomFilter is also a BloomFilter, so
+/// functions generic over `BloomFilter` can borrow a filter rather
+/// than take it.
+impl<T: Hash, B: BloomFilter<T> + ?Sized> BloomFilter<T> for &mut B {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn full_name(&self) -> String {
+        (**self).full_name()
+    }
+
+    fn mark(&mut self, item: &T) {
+        (**self).mark(item)
+    }
+
+    fn check(&self, item: &T) -> bool {
+        (**self).check(item)
+    }
+
+    fn contains(&self, item: &T) -> bool {
+        (**self).contains(item)
+    }
+
+    fn insert(&mut self, item: &T) {
+        (**self).insert(item)
+    }
+
+    fn clear(&mut self) {
+        (**self).clear()
+    }
+
+    fn bulk_check(&self, items: &[T]) -> Vec<bool> {
+        (**self).bulk_check(items)
+    }
+
+    fn try_mark(&mut self, item: &T) -> Result<(), Saturated> {
+        (**self).try_mark(item)
+    }
+
+    fn mark_and_check(&mut self, item: &T) -> bool {
+        (**self).mark_and_check(item)
+    }
+
+    fn insert_if_novel(&mut self, item: &T) -> bool {
+        (**self).insert_if_novel(item)
+    }
+
+    fn mark_many_checked(&mut self, items: &[T]) -> usize {
+        (**self).mark_many_checked(items)
+    }
+
+    fn set_size(&self) -> usize {
+        (**self).set_size()
+    }
+
+    fn bits_per_member(&self) -> usize {
+        (**self).bits_per_member()
+    }
+
+    fn hash_count(&self) -> usize {
+        (**self).hash_count()
+    }
+
+    fn memory_bytes(&self) -> usize {
+        (**self).memory_bytes()
+    }
+
+    fn bytes_per_member(&self) -> f64 {
+        (**self).bytes_per_member()
+    }
+
+    fn summary_string(&self) -> String {
+        (**self).summary_string()
+    }
+
+    fn as_any(&self) -> &dyn Any
+    where
+        Self: 'static,
+    {
+        (**self).as_any()
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any
+    where
+        Self: 'static,
+    {
+        (**self).as_any_mut()
+    }
+
+    fn box_clone(&self) -> Box<dyn BloomFilter<T>>
+    where
+        Self: 'static,
+    {
+        (**self).box_clone()
+    }
+
+    fn parameters(&self) -> BloomParams {
+        (**self).parameters()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use standard::{DefaultStandardBloom, StandardBloom};
+    use blocked::DefaultBlockedBloom;
+    use std::collections::hash_map::DefaultHasher;
+    use super::*;
+
+    #[test]
+    fn parameters_match_accessors() {
+        let bb: DefaultStandardBloom<usize> =
+            DefaultStandardBloom::new(1024, 16, optimal_hashers(16));
+        let p = bb.parameters();
+
+        assert!(p.n == bb.set_size());
+        assert!(p.c == bb.bits_per_member());
+        assert!(p.k == bb.hash_count());
+        assert!(
+            p.false_positive_probability() ==
+                false_positive_probability(p.n, p.c, p.k)
+        );
+    }
+
+    #[test]
+    fn downcast_boxed_filter() {
+        let mut boxed: Box<dyn BloomFilter<usize>> =
+            Box::new(DefaultStandardBloom::new(1024, 16, optimal_hashers(16)));
+        boxed.mark(&100);
+
+        assert!(boxed.as_any().downcast_ref::<DefaultBlockedBloom<usize>>().is_none());
+
+        let standard = boxed
+            .as_any_mut()
+            .downcast_mut::<StandardBloom<DefaultHasher, usize>>()
+            .unwrap();
+        assert!(standard.check(&100));
+    }
+
+    #[test]
+    fn plan_matches_helpers() {
+        let p = plan(1000, 0.01);
+
+        assert!(p.c == optimal_bits_per_member(0.01));
+        assert!(p.k == optimal_hashers(p.c));
+        assert!(p.total_bits == 1000 * p.c);
+        assert!(p.bytes * 8 >= p.total_bits);
+        assert!(false_positive_probability(1000, p.c, p.k) <= 0.01);
+    }
+
+    #[test]
+    fn bytes_per_member_is_sane() {
+        let bb: DefaultStandardBloom<usize> =
+            DefaultStandardBloom::new(1024, 16, optimal_hashers(16));
+
+        assert!(bb.memory_bytes() == 1024 * 16 / 8);
+        assert!((bb.bytes_per_member() - 16.0 / 8.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn mark_many_checked_counts_novel_items() {
+        let mut bb: DefaultStandardBloom<usize> =
+            DefaultStandardBloom::new(1024, 16, optimal_hashers(16));
+
+        assert!(!bb.mark_and_check(&1));
+        assert!(bb.mark_and_check(&1));
+
+        let batch = [1, 2, 3, 2, 4, 4, 4, 5];
+        assert!(bb.mark_many_checked(&batch) == 4);
+        assert!(bb.mark_many_checked(&batch) == 0);
+    }
+
+    #[test]
+    fn contains_agrees_with_check() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(100, 8, 6);
+        for i in 0..100 {
+            bb.mark(&i);
+        }
+
+        assert!((0..1000).all(|i| bb.contains(&i) == bb.check(&i)));
+    }
+
+    #[test]
+    fn boxed_filters_clone_independently() {
+        let mut original: Box<dyn BloomFilter<usize>> =
+            Box::new(DefaultStandardBloom::new(1024, 16, 8));
+        original.mark(&1);
+
+        let mut copy = original.box_clone();
+        copy.mark(&2);
+
+        assert!(original.check(&1) && copy.check(&1));
+        assert!(copy.check(&2) && !original.check(&2));
+        assert!(copy.name() == "standard");
+
+        let blocked: Box<dyn BloomFilter<usize>> =
+            Box::new(DefaultBlockedBloom::new(1024, 16, 8, 4));
+        assert!(blocked.box_clone().name() == "blocked");
+    }
+
+    #[test]
+    fn insert_marks_items() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(100, 8, 6);
+        assert!(!bb.check(&5));
+        bb.insert(&5);
+        assert!(bb.check(&5) && bb.contains(&5));
+    }
+
+    #[test]
+    fn mutable_references_are_filters() {
+        fn mark_range<B: BloomFilter<usize>>(mut bf: B) {
+            for i in 0..100 {
+                bf.mark(&i);
+            }
+        }
+
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(1024, 16, 8);
+        mark_range(&mut bb);
+        assert!((0..100).all(|i| bb.check(&i)));
+
+        let mut blocked: DefaultBlockedBloom<usize> = DefaultBlockedBloom::new(1024, 16, 8, 4);
+        {
+            let dyn_bf: &mut dyn BloomFilter<usize> = &mut blocked;
+            mark_range(dyn_bf);
+        }
+        assert!((0..100).all(|i| blocked.check(&i)));
+    }
+
+    #[test]
+    fn mutable_references_keep_overridden_defaults() {
+        /// A filter that overrides some of the provided methods.
+        #[derive(Clone)]
+        struct Custom(DefaultStandardBloom<usize>);
+
+        impl BloomFilter<usize> for Custom {
+            fn full_name(&self) -> String {
+                "custom".to_string()
+            }
+            fn mark(&mut self, item: &usize) {
+                self.0.mark(item)
+            }
+            fn check(&self, item: &usize) -> bool {
+                self.0.check(item)
+            }
+            fn insert_if_novel(&mut self, _item: &usize) -> bool {
+                true
+            }
+            fn clear(&mut self) {
+                self.0.clear()
+            }
+            fn set_size(&self) -> usize {
+                self.0.set_size()
+            }
+            fn bits_per_member(&self) -> usize {
+                self.0.bits_per_member()
+            }
+            fn hash_count(&self) -> usize {
+                self.0.hash_count()
+            }
+            fn memory_bytes(&self) -> usize {
+                self.0.memory_bytes()
+            }
+            fn parameters(&self) -> BloomParams {
+                BloomParams { n: 1, c: 2, k: 3 }
+            }
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+            fn as_any_mut(&mut self) -> &mut dyn Any {
+                self
+            }
+            fn box_clone(&self) -> Box<dyn BloomFilter<usize>> {
+                Box::new(self.clone())
+            }
+        }
+
+        fn describe<B: BloomFilter<usize>>(mut bf: B) -> (String, BloomParams, usize) {
+            (bf.full_name(), bf.parameters(), bf.mark_many_checked(&[1, 1, 1]))
+        }
+
+        let mut custom = Custom(StandardBloom::new(1024, 16, 8));
+        let (name, params, novel) = describe(&mut custom);
+        assert!(name == "custom");
+        assert!(params == BloomParams { n: 1, c: 2, k: 3 });
+        assert!(novel == 3);
+    }
+
+    #[test]
+    fn insert_if_novel_skips_duplicates() {
+        let mut bb: DefaultStandardBloom<usize> =
+            DefaultStandardBloom::new(1000, 16, optimal_hashers(16));
+
+        // Every item appears three times, spread through the stream.
+        let stream = (0..3000).map(|i| (i * 7) % 1000);
+        let novel = stream.filter(|i| bb.insert_if_novel(i)).count();
+
+        let tolerance = 1000.0 * false_positive_probability(1000, 16, optimal_hashers(16));
+        assert!(novel <= 1000);
+        assert!(novel as f64 >= 1000.0 - 10.0 * tolerance.max(1.0));
+        assert!((0..1000).all(|i| !bb.insert_if_novel(&i)));
+    }
+
+    #[test]
+    fn bulk_check_matches_check() {
+        let mut standard: DefaultStandardBloom<usize> =
+            DefaultStandardBloom::new(1024, 16, optimal_hashers(16));
+        let mut blocked: DefaultBlockedBloom<usize> =
+            DefaultBlockedBloom::new(1024, 16, optimal_hashers(16), 8);
+
+        for i in (0..1024).filter(|i| i % 3 == 0) {
+            standard.mark(&i);
+            blocked.mark(&i);
+        }
+
+        let items: Vec<usize> = (0..2048).collect();
+        let expected: Vec<bool> = items.iter().map(|i| standard.check(i)).collect();
+        assert!(standard.bulk_check(&items) == expected);
+
+        let expected: Vec<bool> = items.iter().map(|i| blocked.check(i)).collect();
+        assert!(blocked.bulk_check(&items) == expected);
+    }
+
+    #[test]
+    fn summary_string_describes_filter() {
+        let bb: DefaultStandardBloom<usize> = DefaultStandardBloom::new(1024, 16, 12);
+        let summary = bb.summary_string();
+
+        assert!(summary.starts_with("standard "));
+        assert!(summary.contains("n=1024"));
+        assert!(summary.contains("c=16"));
+        assert!(summary.contains("k=12"));
+        assert!(summary.contains("fp~"));
+    }
+
+    #[test]
+    fn optimal_hashers_is_exact_for_large_c() {
+        // 6231 * ln(2) is 4319.00008..., which f32 rounds down to 4319.
+        assert!((6231.0f32 * 2.0f32.ln()).ceil() as usize == 4319);
+        assert!(optimal_hashers(6231) == 4320);
+
+        for c in 1..100_000 {
+            let k = optimal_hashers(c) as f64;
+            assert!(k >= c as f64 * 2.0f64.ln() && k - 1.0 < c as f64 * 2.0f64.ln());
+        }
+    }
+
+    #[test]
+    fn optimal_hashers_rounded_follows_policy() {
+        // 10 * ln(2) is 6.93..., and 3 * ln(2) is 2.07...
+        assert!(optimal_hashers_rounded(10, RoundPolicy::Ceil) == 7);
+        assert!(optimal_hashers_rounded(10, RoundPolicy::Floor) == 6);
+        assert!(optimal_hashers_rounded(10, RoundPolicy::Nearest) == 7);
+        assert!(optimal_hashers_rounded(3, RoundPolicy::Nearest) == 2);
+
+        // 1 * ln(2) rounds down to zero, which is clamped to one.
+        assert!(optimal_hashers_rounded(1, RoundPolicy::Floor) == 1);
+
+        for c in 1..1000 {
+            assert!(optimal_hashers_rounded(c, RoundPolicy::Ceil) == optimal_hashers(c));
+        }
+    }
+
+    #[test]
+    fn full_name_includes_parameters() {
+        let bb: DefaultStandardBloom<usize> = StandardBloom::new(1024, 16, 12);
+        assert!(bb.full_name() == "standard[n=1024,c=16,k=12]");
+
+        let bb: DefaultBlockedBloom<usize> = DefaultBlockedBloom::new(2048, 8, 6, 4);
+        assert!(bb.full_name() == "blocked[n=2048,c=8,k=6]");
+    }
+
+    #[test]
+    fn try_mark_respects_max_fill() {
+        let mut blocked: DefaultBlockedBloom<usize> =
+            DefaultBlockedBloom::new(16, 8, optimal_hashers(8), 2);
+        for i in 0..1000 {
+            assert!(blocked.try_mark(&i).is_ok());
+        }
+
+        let mut standard: DefaultStandardBloom<usize> =
+            DefaultStandardBloom::new(16, 8, optimal_hashers(8));
+        standard.set_max_fill(Some(0.5));
+
+        let mut marked = 0;
+        while standard.try_mark(&marked).is_ok() {
+            marked += 1;
+        }
+
+        assert!(marked > 0);
+        // A rejected item leaves the filter as it was.
+        let before = standard.clone();
+        assert!(standard.try_mark(&marked) == Err(Saturated));
+        assert!(standard == before);
+        assert!(standard.fill_ratio() >= 0.5);
+        assert!((0..marked).all(|i| standard.check(&i)));
+    }
 }
@@ -0,0 +1,72 @@
+//! Constructors that build each kind of filter from a single master
+//! seed. Filters built from the same parameters and master seed are
+//! identical, which makes runs reproducible no matter which kind of
+//! filter is in use.
+
+use blocked::{seed_for_block, BlockedBloom};
+use standard::StandardBloom;
+use std::hash::{Hash, Hasher};
+
+/// Create a StandardBloom filter whose seeds are derived from
+/// `master_seed`.
+pub fn seeded_standard<H, T>(n: usize, c: usize, k: usize, master_seed: u64) -> StandardBloom<H, T>
+where
+    H: Hasher + Default,
+    T: Hash,
+{
+    let (seed1, seed2) = seed_for_block(master_seed, 0);
+    StandardBloom::new_with_seeds(n, c, k, seed1, seed2)
+}
+
+/// Create a BlockedBloom filter whose seeds are all derived from
+/// `master_seed`. This is the same as
+/// `BlockedBloom::new_with_master_seed`.
+pub fn seeded_blocked<H, T>(
+    n: usize,
+    c: usize,
+    k: usize,
+    b: usize,
+    master_seed: u64,
+) -> BlockedBloom<H, T>
+where
+    H: Hasher + Default,
+    T: Hash,
+{
+    BlockedBloom::new_with_master_seed(n, c, k, b, master_seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blocked::DefaultBlockedBloom;
+    use bloom::BloomFilter;
+    use standard::DefaultStandardBloom;
+
+    #[test]
+    fn same_seed_standard_filters_are_identical() {
+        let mut a: DefaultStandardBloom<usize> = seeded_standard(1024, 16, 8, 42);
+        let mut b: DefaultStandardBloom<usize> = seeded_standard(1024, 16, 8, 42);
+        let c: DefaultStandardBloom<usize> = seeded_standard(1024, 16, 8, 43);
+        assert!(a == b);
+
+        a.mark(&100);
+        b.mark(&100);
+        assert!(a == b);
+        assert!(a.base_hashes(&100) != c.base_hashes(&100));
+    }
+
+    #[test]
+    fn same_seed_blocked_filters_are_identical() {
+        let mut a: DefaultBlockedBloom<usize> = seeded_blocked(1024, 16, 8, 8, 42);
+        let mut b: DefaultBlockedBloom<usize> = seeded_blocked(1024, 16, 8, 8, 42);
+        for i in 0..1024 {
+            a.mark(&i);
+            b.mark(&i);
+        }
+
+        let probes: Vec<usize> = (0..100_000).collect();
+        assert!(a.block_load(&probes) == b.block_load(&probes));
+        assert!(a.bulk_check(&probes) == b.bulk_check(&probes));
+        assert!(a.block_fill_ratios() == b.block_fill_ratios());
+    }
+}
@@ -0,0 +1,387 @@
+//! A Cuckoo Filter as described in the paper [Cuckoo Filter:
+//! Practically Better Than Bloom by Fan, Andersen, Kaminsky, and
+//! Mitzenmacher](https://www.cs.cmu.edu/~dga/papers/cuckoo-conext2014.pdf).
+//!
+//! Rather than setting bits, a Cuckoo Filter stores a small
+//! fingerprint of each item in one of two candidate buckets. Since the
+//! fingerprints are stored directly, items can be removed again, which
+//! is something a bloom filter can't do.
+
+use index_mask::index_mask;
+use rand::Rng;
+use rand;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std;
+
+/// The number of times an insert will kick a fingerprint out of its
+/// bucket before giving up and declaring the filter full.
+const MAX_KICKS: usize = 500;
+
+/// A fingerprint value of zero marks an empty slot in a bucket.
+const EMPTY: u32 = 0;
+
+/// Returned from `CuckooFilter::insert` when no room could be found
+/// for an item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterFull;
+
+/// A representation of a CuckooFilter.
+///
+/// ```
+/// use baffles::cuckoo::*;
+///
+/// let expected_set_size = 1024 * 1024;
+/// let bucket_size = 4;
+/// let fingerprint_bits = 16;
+///
+/// let mut dcf: DefaultCuckooFilter<usize> = CuckooFilter::new(
+///     expected_set_size,
+///     bucket_size,
+///     fingerprint_bits);
+///
+/// assert!(!dcf.contains(&100));
+/// dcf.insert(&100).unwrap();
+/// assert!(dcf.contains(&100));
+/// dcf.remove(&100);
+/// assert!(!dcf.contains(&100));
+/// ```
+pub struct CuckooFilter<H, T> {
+    /// The fingerprint slots for every bucket, stored one bucket after
+    /// another.
+    slots: Vec<u32>,
+
+    /// The number of fingerprint slots in each bucket.
+    bucket_size: usize,
+
+    /// The number of buckets. This is always a power of two so that
+    /// the alternate bucket of an alternate bucket is the original
+    /// bucket.
+    bucket_count: usize,
+
+    /// A mask selecting the fingerprint bits from a hash.
+    fingerprint_mask: u64,
+
+    /// The hashing function seed to use.
+    seed: u64,
+
+    /// A fingerprint that couldn't be placed during the last failed
+    /// insert, along with one of its candidate buckets. It's kept here
+    /// so that the failed insert doesn't lose a previous member.
+    victim: Option<(usize, u32)>,
+
+    /// The RNG used to pick which fingerprint gets kicked.
+    rng: rand::ThreadRng,
+
+    _p_hasher: PhantomData<H>,
+    _p_type: PhantomData<T>,
+}
+
+/// A CuckooFilter that uses the DefaultHasher.
+pub type DefaultCuckooFilter<T> = CuckooFilter<std::collections::hash_map::DefaultHasher, T>;
+
+impl<H, T> fmt::Debug for CuckooFilter<H, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CuckooFilter {{ buckets: {}, bucket_size: {}, slots: {:?} }}",
+            self.bucket_count,
+            self.bucket_size,
+            self.slots
+        )
+    }
+}
+
+impl<H: Hasher + Default, T: Hash> CuckooFilter<H, T> {
+    /// Create a new CuckooFilter.
+    ///
+    /// * `n`: estimate of the number of items in the set
+    /// * `bucket_size`: number of fingerprints stored in each bucket
+    /// * `fingerprint_bits`: width of each fingerprint, from 1 to 32
+    ///
+    /// The number of buckets is rounded up to a power of two large
+    /// enough to hold `n` fingerprints. Wider fingerprints lower the
+    /// false-positive rate.
+    pub fn new(n: usize, bucket_size: usize, fingerprint_bits: usize) -> Self {
+        let mut rng = rand::thread_rng();
+        let seed = rng.gen::<u64>();
+        CuckooFilter::new_with_seed(n, bucket_size, fingerprint_bits, seed)
+    }
+
+    /// Like `new`, but allows the specification of the seed to use
+    /// for the hasher.
+    pub fn new_with_seed(
+        n: usize,
+        bucket_size: usize,
+        fingerprint_bits: usize,
+        seed: u64,
+    ) -> CuckooFilter<H, T> {
+        assert!(n > 0);
+        assert!(bucket_size > 0);
+        assert!(fingerprint_bits > 0);
+        assert!(fingerprint_bits <= 32);
+
+        let buckets_needed = n.div_ceil(bucket_size);
+        let bucket_count = index_mask(buckets_needed as u64 - 1) as usize + 1;
+
+        CuckooFilter {
+            slots: vec![EMPTY; bucket_count * bucket_size],
+            bucket_size,
+            bucket_count,
+            fingerprint_mask: (1 << fingerprint_bits) - 1,
+            seed,
+            victim: None,
+            rng: rand::thread_rng(),
+
+            _p_hasher: PhantomData,
+            _p_type: PhantomData,
+        }
+    }
+
+    /// Add `item` to the CuckooFilter.
+    ///
+    /// `FilterFull` means the table is full. If the item's buckets were
+    /// full, its fingerprint may still have been stored by evicting
+    /// another one, and that evicted fingerprint is held in a victim
+    /// slot so its item can still be found. So `contains` may be true
+    /// for `item` even though inserting it failed. While the victim
+    /// slot is occupied, every `insert` fails with `FilterFull` until a
+    /// `remove` makes room for the victim.
+    pub fn insert(&mut self, item: &T) -> Result<(), FilterFull> {
+        if self.victim.is_some() {
+            return Err(FilterFull);
+        }
+
+        let (fp, i1) = self.fingerprint_and_index(item);
+        let i2 = self.alt_index(i1, fp);
+
+        if self.place(i1, fp) || self.place(i2, fp) {
+            return Ok(());
+        }
+
+        // Both buckets are full. Evict a random fingerprint from one
+        // of them and try to move it to its own alternate bucket,
+        // repeating until something lands in an empty slot.
+        let mut idx = if self.rng.gen::<bool>() { i1 } else { i2 };
+        let mut fp = fp;
+
+        for _ in 0..MAX_KICKS {
+            let slot = idx * self.bucket_size + self.rng.gen_range(0, self.bucket_size);
+            std::mem::swap(&mut fp, &mut self.slots[slot]);
+
+            idx = self.alt_index(idx, fp);
+            if self.place(idx, fp) {
+                return Ok(());
+            }
+        }
+
+        // The fingerprint in hand belongs to an item that was already
+        // a member (or the one being inserted). Hold on to it so it
+        // can still be found.
+        self.victim = Some((idx, fp));
+        Err(FilterFull)
+    }
+
+    /// True if `item` may be a member of the CuckooFilter.
+    pub fn contains(&self, item: &T) -> bool {
+        let (fp, i1) = self.fingerprint_and_index(item);
+        let i2 = self.alt_index(i1, fp);
+
+        let in_victim = match self.victim {
+            Some((idx, vfp)) => vfp == fp && (idx == i1 || idx == i2),
+            None => false,
+        };
+
+        in_victim || self.bucket(i1).contains(&fp) || self.bucket(i2).contains(&fp)
+    }
+
+    /// Remove `item` from the CuckooFilter. Returns true if a
+    /// fingerprint for the item was found and removed. Only items that
+    /// were previously inserted should be removed, otherwise the
+    /// fingerprint of a different item may be removed instead.
+    pub fn remove(&mut self, item: &T) -> bool {
+        let (fp, i1) = self.fingerprint_and_index(item);
+        let i2 = self.alt_index(i1, fp);
+
+        if let Some((idx, vfp)) = self.victim {
+            if vfp == fp && (idx == i1 || idx == i2) {
+                self.victim = None;
+                return true;
+            }
+        }
+
+        let removed = self.unplace(i1, fp) || self.unplace(i2, fp);
+
+        if removed {
+            // Now that there's an empty slot, the victim may fit.
+            if let Some((idx, vfp)) = self.victim.take() {
+                if !self.place(idx, vfp) {
+                    let alt = self.alt_index(idx, vfp);
+                    if !self.place(alt, vfp) {
+                        self.victim = Some((idx, vfp));
+                    }
+                }
+            }
+        }
+
+        removed
+    }
+
+    /// Check the filter's internal invariants, returning a description
+    /// of the first one that doesn't hold. A filter built by this crate
+    /// always validates; this is meant for catching corruption.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.bucket_size == 0 {
+            return Err("buckets have no slots");
+        }
+        if !self.bucket_count.is_power_of_two() {
+            return Err("the number of buckets isn't a power of two");
+        }
+        if self.slots.len() != self.bucket_count * self.bucket_size {
+            return Err("the number of slots doesn't match the buckets");
+        }
+        if self.fingerprint_mask == 0 || self.fingerprint_mask > u32::MAX as u64 {
+            return Err("fingerprints must be between 1 and 32 bits");
+        }
+        if self.fingerprint_mask & (self.fingerprint_mask + 1) != 0 {
+            return Err("the fingerprint mask isn't a run of low bits");
+        }
+        if self.slots.iter().any(|s| *s as u64 & !self.fingerprint_mask != 0) {
+            return Err("a slot holds a fingerprint wider than the mask");
+        }
+        if let Some((idx, fp)) = self.victim {
+            if idx >= self.bucket_count || fp == EMPTY {
+                return Err("the victim isn't a valid fingerprint and bucket");
+            }
+        }
+        Ok(())
+    }
+
+    /// The slots of the bucket at index `idx`.
+    fn bucket(&self, idx: usize) -> &[u32] {
+        let start = idx * self.bucket_size;
+        &self.slots[start..start + self.bucket_size]
+    }
+
+    /// Store `fp` in an empty slot of bucket `idx`. Returns false if
+    /// the bucket has no empty slots.
+    fn place(&mut self, idx: usize, fp: u32) -> bool {
+        let start = idx * self.bucket_size;
+        match self.slots[start..start + self.bucket_size]
+            .iter_mut()
+            .find(|s| **s == EMPTY) {
+            Some(s) => {
+                *s = fp;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Clear one slot holding `fp` in bucket `idx`. Returns false if
+    /// the bucket doesn't contain `fp`.
+    fn unplace(&mut self, idx: usize, fp: u32) -> bool {
+        let start = idx * self.bucket_size;
+        match self.slots[start..start + self.bucket_size]
+            .iter_mut()
+            .find(|s| **s == fp) {
+            Some(s) => {
+                *s = EMPTY;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Calculate the fingerprint and the first candidate bucket index
+    /// of `item`.
+    fn fingerprint_and_index(&self, item: &T) -> (u32, usize) {
+        let mut h: H = Default::default();
+        h.write_u64(self.seed);
+        item.hash(&mut h);
+        let hash = h.finish();
+
+        // The low bits select the bucket and the high bits make up the
+        // fingerprint. A fingerprint of zero would look like an empty
+        // slot, so it's nudged to one.
+        let fp = ((hash >> 32) & self.fingerprint_mask) as u32;
+        let fp = if fp == EMPTY { 1 } else { fp };
+        let idx = (hash & (self.bucket_count as u64 - 1)) as usize;
+
+        (fp, idx)
+    }
+
+    /// Calculate the other candidate bucket for a fingerprint stored
+    /// in bucket `idx`. This is the partial-key cuckoo hashing trick:
+    /// since only the fingerprint is needed, a fingerprint can be
+    /// moved without knowing the item it came from.
+    fn alt_index(&self, idx: usize, fp: u32) -> usize {
+        let mut h: H = Default::default();
+        h.write_u64(self.seed);
+        h.write_u32(fp);
+
+        (idx ^ (h.finish() as usize)) & (self.bucket_count - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_contains_remove() {
+        let mut cf: DefaultCuckooFilter<usize> = CuckooFilter::new(1024, 4, 16);
+
+        for i in 0..512 {
+            assert!(cf.insert(&i).is_ok());
+        }
+
+        for i in 0..512 {
+            assert!(cf.contains(&i));
+        }
+
+        for i in 0..256 {
+            assert!(cf.remove(&i));
+        }
+
+        for i in 256..512 {
+            assert!(cf.contains(&i));
+        }
+    }
+
+    #[test]
+    fn full_table_is_an_error() {
+        let mut cf: DefaultCuckooFilter<usize> = CuckooFilter::new(8, 1, 16);
+
+        let mut inserted = Vec::new();
+        let mut result = Ok(());
+        for i in 0..64 {
+            result = cf.insert(&i);
+            if result.is_err() {
+                break;
+            }
+            inserted.push(i);
+        }
+
+        assert!(result == Err(FilterFull));
+        assert!(cf.insert(&1000) == Err(FilterFull));
+
+        // A failed insert must not cause any member to go missing.
+        for i in inserted {
+            assert!(cf.contains(&i));
+        }
+    }
+
+    #[test]
+    fn corrupted_filter_fails_validation() {
+        let mut cf: DefaultCuckooFilter<usize> = CuckooFilter::new(1024, 4, 16);
+        for i in 0..512 {
+            cf.insert(&i).unwrap();
+        }
+        assert!(cf.validate() == Ok(()));
+
+        cf.slots.pop();
+        assert!(cf.validate().is_err());
+    }
+}
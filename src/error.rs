@@ -0,0 +1,62 @@
+//! The error type shared by the fallible operations of the filters.
+
+use standard::MergeError;
+use std::error::Error;
+use std::fmt;
+
+/// Everything that can go wrong in a fallible filter operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BloomError {
+    /// The parameters given can't describe a filter.
+    InvalidParameters(&'static str),
+
+    /// Two filters can't be combined.
+    Incompatible(MergeError),
+
+    /// Bytes couldn't be read back as a filter.
+    Deserialize(&'static str),
+
+    /// A size calculation overflowed.
+    Overflow,
+}
+
+impl fmt::Display for BloomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BloomError::InvalidParameters(why) => write!(f, "invalid parameters: {}", why),
+            BloomError::Incompatible(ref e) => write!(f, "incompatible filters: {}", e),
+            BloomError::Deserialize(why) => write!(f, "unable to deserialize filter: {}", why),
+            BloomError::Overflow => write!(f, "filter size overflowed"),
+        }
+    }
+}
+
+impl Error for BloomError {}
+
+impl From<MergeError> for BloomError {
+    fn from(e: MergeError) -> BloomError {
+        BloomError::Incompatible(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_each_variant() {
+        assert!(
+            BloomError::InvalidParameters("k must be positive").to_string() ==
+                "invalid parameters: k must be positive"
+        );
+        assert!(
+            BloomError::from(MergeError::MismatchedSeeds).to_string() ==
+                "incompatible filters: the filters use different seeds"
+        );
+        assert!(
+            BloomError::Deserialize("too short").to_string() ==
+                "unable to deserialize filter: too short"
+        );
+        assert!(BloomError::Overflow.to_string() == "filter size overflowed");
+    }
+}
@@ -0,0 +1,388 @@
+//! A Golomb-Compressed Set: a static, read-only alternative to a bloom
+//! filter that takes close to the fewest bits possible for its false
+//! positive rate.
+//!
+//! Each item is hashed to a fingerprint in `0..n * 2^r`. The
+//! fingerprints are sorted, and the gaps between them are stored with
+//! Golomb-Rice coding: the gap divided by `2^r` in unary, followed by
+//! the low `r` bits of the gap. The set can't be changed once built.
+//!
+//! Every `INDEX_INTERVAL`th fingerprint is also recorded in a sparse
+//! index along with where its gap starts, so a check only decodes the
+//! gaps between two index entries rather than every gap from the
+//! start.
+//!
+//! A set implements `BloomFilter` so it can stand in for a filter that
+//! is only checked, but `mark` panics. A bloom filter doesn't remember
+//! the items marked in it, so a set can only be built from the items
+//! themselves, not from a finished `StandardBloom`.
+
+use bit_array::BitArray;
+use bloom::BloomFilter;
+use rand::Rng;
+use rand;
+use std::any::Any;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std;
+
+/// The number of fingerprints between entries in the sparse index.
+const INDEX_INTERVAL: usize = 128;
+
+/// A representation of a GolombCompressedSet.
+///
+/// ```
+/// use baffles::golomb::*;
+///
+/// let items: Vec<usize> = (0..1000).collect();
+/// let gcs: DefaultGolombCompressedSet<usize> = GolombCompressedSet::from_items(&items, 0.01);
+///
+/// assert!(gcs.check(&100));
+/// ```
+pub struct GolombCompressedSet<H, T> {
+    /// The Rice-coded gaps between the sorted fingerprints.
+    bits: BitArray,
+
+    /// The number of distinct fingerprints stored.
+    count: usize,
+
+    /// Fingerprints are in `0..range`.
+    range: u64,
+
+    /// The number of low bits of each gap stored directly, `r`.
+    rice_bits: u32,
+
+    /// For every `INDEX_INTERVAL`th fingerprint, the fingerprint before
+    /// it (or 0 for the first) and the bit position of its gap.
+    index: Vec<(u64, usize)>,
+
+    /// The hashing function seed to use.
+    seed: u64,
+
+    _p_hasher: PhantomData<H>,
+    _p_type: PhantomData<T>,
+}
+
+/// A GolombCompressedSet that uses the DefaultHasher.
+pub type DefaultGolombCompressedSet<T> =
+    GolombCompressedSet<std::collections::hash_map::DefaultHasher, T>;
+
+impl<H, T> fmt::Debug for GolombCompressedSet<H, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "GolombCompressedSet {{ fingerprints: {}, rice_bits: {}, encoded_bits: {} }}",
+            self.count,
+            self.rice_bits,
+            self.bits.width()
+        )
+    }
+}
+
+impl<H, T> Clone for GolombCompressedSet<H, T> {
+    fn clone(&self) -> Self {
+        GolombCompressedSet {
+            bits: self.bits.clone(),
+            count: self.count,
+            range: self.range,
+            rice_bits: self.rice_bits,
+            index: self.index.clone(),
+            seed: self.seed,
+
+            _p_hasher: PhantomData,
+            _p_type: PhantomData,
+        }
+    }
+}
+
+impl<H: Hasher + Default, T: Hash> GolombCompressedSet<H, T> {
+    /// Build a set holding every item in `items` with a false positive
+    /// probability of about `fp_rate`.
+    pub fn from_items(items: &[T], fp_rate: f64) -> Self {
+        let mut rng = rand::thread_rng();
+        GolombCompressedSet::from_items_with_seed(items, fp_rate, rng.gen::<u64>())
+    }
+
+    /// Like `from_items`, but allows the specification of the seed to
+    /// use for the hasher.
+    pub fn from_items_with_seed(items: &[T], fp_rate: f64, seed: u64) -> Self {
+        assert!(fp_rate > 0.0 && fp_rate < 1.0);
+
+        // Each item gets `2^r` fingerprints to itself, so a fingerprint
+        // outside the set matches a member with probability `1 / 2^r`.
+        let rice_bits = (1.0 / fp_rate).log2().ceil() as u32;
+        assert!(rice_bits < 64);
+        let range = (items.len() as u64)
+            .checked_mul(1 << rice_bits)
+            .expect("too many items for the false positive rate");
+
+        let mut fingerprints: Vec<u64> =
+            items.iter().map(|item| fingerprint::<H, T>(seed, range, item)).collect();
+        fingerprints.sort_unstable();
+        fingerprints.dedup();
+
+        let mut encoded_bits = 0;
+        let mut previous = 0;
+        for fp in &fingerprints {
+            encoded_bits += ((fp - previous) >> rice_bits) as usize + 1 + rice_bits as usize;
+            previous = *fp;
+        }
+
+        // A BitArray can't be empty, so an empty set still has one bit.
+        let mut bits = BitArray::new(encoded_bits.max(1));
+        let mut index = Vec::with_capacity(fingerprints.len().div_ceil(INDEX_INTERVAL));
+        let mut pos = 0;
+        let mut previous = 0;
+        for (i, fp) in fingerprints.iter().enumerate() {
+            if i % INDEX_INTERVAL == 0 {
+                index.push((previous, pos));
+            }
+
+            let gap = fp - previous;
+            previous = *fp;
+
+            let quotient = (gap >> rice_bits) as usize;
+            bits.set_range(pos, pos + quotient);
+            pos += quotient + 1;
+
+            for i in 0..rice_bits {
+                bits.set_to(pos, gap >> (rice_bits - 1 - i) & 1 == 1);
+                pos += 1;
+            }
+        }
+
+        GolombCompressedSet {
+            bits,
+            count: fingerprints.len(),
+            range,
+            rice_bits,
+            index,
+            seed,
+
+            _p_hasher: PhantomData,
+            _p_type: PhantomData,
+        }
+    }
+
+    /// True if `item` may be in the set, false if it definitely isn't.
+    /// This decodes at most `INDEX_INTERVAL` fingerprints, starting
+    /// from the last index entry that comes before the item's.
+    pub fn check(&self, item: &T) -> bool {
+        if self.count == 0 {
+            return false;
+        }
+
+        let target = fingerprint::<H, T>(self.seed, self.range, item);
+
+        // The first fingerprint of an entry is larger than the entry's
+        // `previous`, so the target can only be found from the last
+        // entry whose `previous` is smaller than it.
+        let entry = self
+            .index
+            .partition_point(|&(previous, _)| previous < target)
+            .saturating_sub(1);
+        let (mut value, mut pos) = self.index[entry];
+        let remaining = (self.count - entry * INDEX_INTERVAL).min(INDEX_INTERVAL);
+
+        for _ in 0..remaining {
+            let mut quotient = 0;
+            while self.bits.get(pos) {
+                quotient += 1;
+                pos += 1;
+            }
+            pos += 1;
+
+            let mut remainder = 0;
+            for _ in 0..self.rice_bits {
+                remainder = remainder << 1 | self.bits.get(pos) as u64;
+                pos += 1;
+            }
+
+            value += quotient << self.rice_bits | remainder;
+            if value >= target {
+                return value == target;
+            }
+        }
+
+        false
+    }
+
+    /// The number of distinct fingerprints stored. Items whose
+    /// fingerprints collide are only stored once.
+    pub fn fingerprint_count(&self) -> usize {
+        self.count
+    }
+
+    /// The number of bytes used to store the encoded fingerprints and
+    /// the index into them.
+    pub fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self.bits.as_words()) + std::mem::size_of_val(&self.index[..])
+    }
+}
+
+/// A set can be checked like any other filter, but it can't be changed
+/// once built: `mark` panics, and so does anything that marks, such as
+/// `insert` and `try_mark`. `clear` leaves an empty set.
+impl<H, T> BloomFilter<T> for GolombCompressedSet<H, T>
+where
+    H: Hasher + Default,
+    T: Hash,
+{
+    fn name(&self) -> &str {
+        "golomb"
+    }
+
+    fn mark(&mut self, _item: &T) {
+        panic!("a GolombCompressedSet can't be marked once built");
+    }
+
+    fn check(&self, item: &T) -> bool {
+        GolombCompressedSet::check(self, item)
+    }
+
+    fn clear(&mut self) {
+        self.bits = BitArray::new(1);
+        self.count = 0;
+        self.range = 0;
+        self.index.clear();
+    }
+
+    /// The number of items the set was built from.
+    fn set_size(&self) -> usize {
+        (self.range >> self.rice_bits) as usize
+    }
+
+    /// The number of encoded bits per item, rounded up.
+    fn bits_per_member(&self) -> usize {
+        self.bits.width().div_ceil(self.set_size().max(1))
+    }
+
+    /// Each item is hashed once, to its fingerprint.
+    fn hash_count(&self) -> usize {
+        1
+    }
+
+    fn memory_bytes(&self) -> usize {
+        GolombCompressedSet::memory_bytes(self)
+    }
+
+    fn as_any(&self) -> &dyn Any
+    where
+        Self: 'static,
+    {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any
+    where
+        Self: 'static,
+    {
+        self
+    }
+
+    fn box_clone(&self) -> Box<dyn BloomFilter<T>>
+    where
+        Self: 'static,
+    {
+        Box::new(self.clone())
+    }
+}
+
+/// Hash `item` to a fingerprint in `0..range`.
+fn fingerprint<H: Hasher + Default, T: Hash>(seed: u64, range: u64, item: &T) -> u64 {
+    let mut h: H = Default::default();
+    h.write_u64(seed);
+    item.hash(&mut h);
+
+    ((h.finish() as u128 * range as u128) >> 64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use standard::{DefaultStandardBloom, StandardBloom};
+    use std::collections::hash_map::DefaultHasher;
+
+    #[test]
+    fn members_are_found_in_less_space_than_a_bloom_filter() {
+        let items: Vec<usize> = (0..2000).collect();
+        let gcs: DefaultGolombCompressedSet<usize> =
+            GolombCompressedSet::from_items_with_seed(&items, 0.01, 7);
+        assert!(items.iter().all(|i| gcs.check(i)));
+
+        let false_positives = (2000..4000).filter(|i| gcs.check(i)).count();
+        assert!(false_positives < 50);
+
+        let bb: DefaultStandardBloom<usize> = StandardBloom::built_from(&items, 0.01);
+        assert!(gcs.memory_bytes() < bb.memory_bytes());
+    }
+
+    #[test]
+    fn indexed_checks_match_a_linear_decode() {
+        let items: Vec<usize> = (0..1000).collect();
+        let gcs: DefaultGolombCompressedSet<usize> =
+            GolombCompressedSet::from_items_with_seed(&items, 0.05, 3);
+        assert!(gcs.index.len() == gcs.count.div_ceil(INDEX_INTERVAL));
+
+        // Decode every fingerprint from the start.
+        let mut fingerprints = Vec::new();
+        let (mut pos, mut value) = (0, 0);
+        for _ in 0..gcs.count {
+            let mut quotient = 0;
+            while gcs.bits.get(pos) {
+                quotient += 1;
+                pos += 1;
+            }
+            pos += 1;
+
+            let mut remainder = 0;
+            for _ in 0..gcs.rice_bits {
+                remainder = remainder << 1 | gcs.bits.get(pos) as u64;
+                pos += 1;
+            }
+
+            value += quotient << gcs.rice_bits | remainder;
+            fingerprints.push(value);
+        }
+
+        for i in 0..5000 {
+            let fp = fingerprint::<DefaultHasher, usize>(gcs.seed, gcs.range, &i);
+            assert!(gcs.check(&i) == fingerprints.binary_search(&fp).is_ok());
+        }
+    }
+
+    #[test]
+    fn checks_through_the_bloom_filter_trait() {
+        let items: Vec<usize> = (0..500).collect();
+        let mut gcs: DefaultGolombCompressedSet<usize> =
+            GolombCompressedSet::from_items_with_seed(&items, 0.01, 7);
+
+        {
+            let filter: &dyn BloomFilter<usize> = &gcs;
+            assert!(filter.name() == "golomb");
+            assert!(filter.set_size() == 500);
+            assert!(items.iter().all(|i| filter.check(i)));
+            assert!(filter.bulk_check(&items).into_iter().all(|found| found));
+        }
+
+        BloomFilter::clear(&mut gcs);
+        assert!(gcs.set_size() == 0);
+        assert!(!items.iter().any(|i| gcs.check(i)));
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be marked")]
+    fn marking_a_set_panics() {
+        let mut gcs: DefaultGolombCompressedSet<usize> =
+            GolombCompressedSet::from_items(&[1], 0.01);
+        BloomFilter::mark(&mut gcs, &2);
+    }
+
+    #[test]
+    fn empty_set_has_no_members() {
+        let gcs: DefaultGolombCompressedSet<usize> = GolombCompressedSet::from_items(&[], 0.01);
+        assert!(gcs.fingerprint_count() == 0);
+        assert!(!gcs.check(&0));
+    }
+}
@@ -3,30 +3,44 @@ use std::hash::Hasher;
 /// Starting with `initial`, continue passing the output of the hasher
 /// into itself until `prop` returns true for one of them.
 pub fn hash_until<H: Hasher, F: Fn(u64) -> bool>(h: &mut H, initial: u64, prop: F) -> u64 {
-    if prop(initial) {
-        // If the initial hash value already meets our constraint, it
-        // is our result. We don't need to do any more work.
-        initial
-    } else {
-        // If the initial hash value does not meet our constraint, then
-        // we'll create a new hasher and seed it with our initial value.
-        h.write_u64(initial);
-        let mut r = h.finish();
-
-        loop {
-            // Now we'll keep feeding the result of the hash back into
-            // the hasher until we get a value that fits our
-            // constraint.
-            if prop(r) {
-                break;
-            } else {
-                h.write_u64(r);
-                r = h.finish();
-            }
+    hash_until_counted(h, initial, prop).0
+}
+
+/// Like `hash_until`, but also returns the number of times a hash was
+/// rejected by `prop` before one was accepted.
+pub fn hash_until_counted<H: Hasher, F: Fn(u64) -> bool>(
+    h: &mut H,
+    initial: u64,
+    prop: F,
+) -> (u64, usize) {
+    hash_until_bounded(h, initial, prop, usize::MAX).expect("rejections overflowed a usize")
+}
+
+/// Like `hash_until_counted`, but gives up and returns `None` once more
+/// than `max_rejections` hashes have been rejected.
+pub fn hash_until_bounded<H: Hasher, F: Fn(u64) -> bool>(
+    h: &mut H,
+    initial: u64,
+    prop: F,
+    max_rejections: usize,
+) -> Option<(u64, usize)> {
+    let mut r = initial;
+    let mut rejections = 0;
+
+    // Keep feeding the result of the hash back into the hasher until
+    // we get a value that fits our constraint. If the initial hash
+    // value already meets it, no more work is needed.
+    while !prop(r) {
+        if rejections == max_rejections {
+            return None;
         }
 
-        r
+        h.write_u64(r);
+        r = h.finish();
+        rejections += 1;
     }
+
+    Some((r, rejections))
 }
 
 #[cfg(test)]
@@ -38,8 +52,31 @@ mod tests {
         let mut h: DefaultHasher = Default::default();
         let prop = |h| (0xFF & h) > 128;
 
-        assert!(129 == hash_until(&mut h, 129, &prop));
-        assert!(128 < (0xFF & hash_until(&mut h, 127, &prop)));
+        assert!(129 == hash_until(&mut h, 129, prop));
+        assert!(128 < (0xFF & hash_until(&mut h, 127, prop)));
+
+    }
+
+    #[test]
+    fn test_hash_until_counted() {
+        let mut h: DefaultHasher = Default::default();
+        let prop = |h| (0xFF & h) > 128;
+
+        assert!((129, 0) == hash_until_counted(&mut h, 129, prop));
+
+        let mut h1: DefaultHasher = Default::default();
+        let mut h2: DefaultHasher = Default::default();
+        let (r, rejections) = hash_until_counted(&mut h1, 127, prop);
+        assert!(r == hash_until(&mut h2, 127, prop));
+        assert!(rejections >= 1);
+    }
+
+    #[test]
+    fn test_hash_until_bounded() {
+        let mut h: DefaultHasher = Default::default();
+        let never = |_| false;
 
+        assert!(hash_until_bounded(&mut h, 1, never, 10).is_none());
+        assert!(hash_until_bounded(&mut h, 129, |h| h > 128, 0) == Some((129, 0)));
     }
 }
@@ -1,19 +1,43 @@
 /// Calculate a mask suitable for representing all bits of a
 /// value. (There are faster ways to do this, but we don't calculate
 /// this often, so we're using the obvious approach.)
+///
+/// The mask is the smallest `2^i - 1` that is at least `value`. When
+/// `value` is the largest index of a range with a power-of-two length,
+/// the mask covers the range exactly and a masked hash is always a
+/// valid index. Just past a power of two, the mask doubles, and nearly
+/// half of all masked hashes fall outside of the range.
 pub fn index_mask(value: u64) -> u64 {
-    (1..64)
+    (0..64)
         .map(|i| (1 << i) - 1)
-        .find(|m| *m >= value as u64)
-        .unwrap_or(u64::max_value())
+        .find(|m| *m >= value)
+        .unwrap_or(u64::MAX)
 }
 
 #[test]
 fn test_index_mask() {
-    assert!(u64::max_value() == index_mask(1 << 63));
-    assert!(u64::max_value() == index_mask(10 + (1 << 63)));
+    assert!(u64::MAX == index_mask(1 << 63));
+    assert!(u64::MAX == index_mask(10 + (1 << 63)));
     assert!((1 << 62) - 1 == index_mask((1 << 61) + 424242));
-    assert!(1 == index_mask(0));
+    assert!(0 == index_mask(0));
     assert!(1 == index_mask(1));
     assert!(3 == index_mask(2));
 }
+
+#[test]
+fn test_index_mask_at_powers_of_two() {
+    // A range with exactly one index needs no bits at all.
+    assert!(0 == index_mask(0));
+
+    for i in 1..64 {
+        let pow2: u64 = 1 << i;
+
+        // `pow2` indices, the largest being `pow2 - 1`, are covered
+        // exactly by the mask.
+        assert!(pow2 - 1 == index_mask(pow2 - 1));
+
+        // One more index than that needs one more bit.
+        let next = if i == 63 { u64::MAX } else { (pow2 << 1) - 1 };
+        assert!(next == index_mask(pow2));
+    }
+}
@@ -1,9 +1,67 @@
+//! Bloom filters, blocked bloom filters, a cuckoo filter, and a
+//! Golomb-compressed set.
+//!
+//! The most common types and functions are available from the crate
+//! root:
+//!
+//! ```
+//! use baffles::{optimal_hashers, BloomFilter, DefaultBlockedBloom, DefaultStandardBloom};
+//!
+//! let c = 16;
+//! let mut standard: DefaultStandardBloom<usize> =
+//!     DefaultStandardBloom::new(1024, c, optimal_hashers(c));
+//! let mut blocked: DefaultBlockedBloom<usize> =
+//!     DefaultBlockedBloom::new(1024, c, optimal_hashers(c), 8);
+//!
+//! standard.mark(&100);
+//! blocked.mark(&100);
+//! assert!(standard.check(&100) && blocked.check(&100));
+//! ```
+
+#[cfg(feature = "ahash")]
+extern crate ahash;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
 extern crate rand;
+#[cfg(feature = "rayon")]
+extern crate rayon;
 
-mod bit_array;
 mod hash_until;
 mod index_mask;
+mod splitmix;
 
 pub mod standard;
 pub mod blocked;
 pub mod bloom;
+pub mod cuckoo;
+pub mod error;
+pub mod reducer;
+pub mod any_bloom;
+pub mod bit_array;
+pub mod sync;
+pub mod ctor;
+pub mod golomb;
+#[cfg(feature = "verified")]
+pub mod verified;
+
+pub use blocked::{BlockedBloom, DefaultBlockedBloom};
+pub use bloom::{
+    false_positive_probability, optimal_bits_per_member, optimal_hashers, plan, BloomFilter,
+};
+pub use standard::{DefaultStandardBloom, StandardBloom};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_blocked_bloom_is_the_blocked_module_type() {
+        let mut root: BlockedBloom<std::collections::hash_map::DefaultHasher, usize> =
+            BlockedBloom::new(1024, 16, 8, 4);
+        root.mark(&100);
+        assert!(root.check(&100));
+
+        let as_module: &blocked::BlockedBloom<_, usize> = &root;
+        assert!(as_module.name() == "blocked");
+    }
+}
@@ -0,0 +1,234 @@
+//! Strategies for reducing a 64-bit hash to an index into a smaller
+//! range, such as the bits of a StandardBloom filter.
+
+use hash_until::hash_until_bounded;
+use index_mask::index_mask;
+use std::hash::Hasher;
+
+/// A way of turning a hash into an index in `0..range`. Reducers are
+/// cloned along with the filters that use them.
+pub trait IndexReducer: Clone {
+    /// Create a reducer that produces indices in `0..range`.
+    fn new(range: usize) -> Self;
+
+    /// The number of indices the reducer produces.
+    fn range(&self) -> usize;
+
+    /// Check that the reducer can only produce indices in its range.
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.range() == 0 {
+            return Err("the reducer's range is empty");
+        }
+        Ok(())
+    }
+
+    /// Reduce `hash` to an index in `0..range`. `h` can be used to
+    /// derive more hashes from `hash` if it isn't usable on its own.
+    fn reduce<H: Hasher>(&self, h: &mut H, hash: u64) -> usize;
+
+    /// Like `reduce`, but also returns the number of derived hashes
+    /// that were rejected along the way. Reducers that never reject a
+    /// hash report zero.
+    fn reduce_counted<H: Hasher>(&self, h: &mut H, hash: u64) -> (usize, usize) {
+        (self.reduce(h, hash), 0)
+    }
+
+    /// Like `reduce_counted`, but gives up and returns `None` once more
+    /// than `max_rejections` derived hashes have been rejected.
+    fn reduce_bounded<H: Hasher>(
+        &self,
+        h: &mut H,
+        hash: u64,
+        _max_rejections: usize,
+    ) -> Option<(usize, usize)> {
+        Some(self.reduce_counted(h, hash))
+    }
+}
+
+/// Mask off the top bits of the hash, and if the result is out of
+/// range, hash the hash again until it isn't. Every index is equally
+/// likely, but the number of hashes needed isn't fixed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RejectionSampling {
+    /// The number of indices.
+    range: usize,
+
+    /// A mask able to represent every index in the range.
+    mask: u64,
+}
+
+impl RejectionSampling {
+    /// The mask applied to each hash.
+    pub fn mask(&self) -> u64 {
+        self.mask
+    }
+}
+
+impl IndexReducer for RejectionSampling {
+    fn new(range: usize) -> Self {
+        assert!(range > 0);
+
+        RejectionSampling {
+            range,
+            mask: index_mask(range as u64 - 1),
+        }
+    }
+
+    fn range(&self) -> usize {
+        self.range
+    }
+
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.range == 0 {
+            return Err("the reducer's range is empty");
+        }
+        if (self.range - 1) as u64 & !self.mask != 0 {
+            return Err("the mask doesn't cover every index");
+        }
+        Ok(())
+    }
+
+    fn reduce<H: Hasher>(&self, h: &mut H, hash: u64) -> usize {
+        self.reduce_counted(h, hash).0
+    }
+
+    fn reduce_counted<H: Hasher>(&self, h: &mut H, hash: u64) -> (usize, usize) {
+        self.reduce_bounded(h, hash, usize::MAX)
+            .expect("rejections overflowed a usize")
+    }
+
+    fn reduce_bounded<H: Hasher>(
+        &self,
+        h: &mut H,
+        hash: u64,
+        max_rejections: usize,
+    ) -> Option<(usize, usize)> {
+        // When the range is a power of two, every masked hash is in
+        // range and there's nothing to reject.
+        if self.mask == (self.range - 1) as u64 {
+            return Some(((self.mask & hash) as usize, 0));
+        }
+
+        // This will be true when the hash can be used to produce an
+        // index in the range.
+        let prop = |v| (self.mask & v) <= (self.range - 1) as u64;
+
+        // This hash, when masked, will give us a usable index.
+        let (usable_hash, rejections) = hash_until_bounded(h, hash, prop, max_rejections)?;
+
+        Some(((self.mask & usable_hash) as usize, rejections))
+    }
+}
+
+/// Take the remainder of the hash divided by the range. This never
+/// needs more than one hash, but when the range doesn't evenly divide
+/// 2^64, the lowest indices are very slightly more likely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modulo {
+    /// The number of indices.
+    range: usize,
+}
+
+impl IndexReducer for Modulo {
+    fn new(range: usize) -> Self {
+        assert!(range > 0);
+
+        Modulo { range }
+    }
+
+    fn range(&self) -> usize {
+        self.range
+    }
+
+    fn reduce<H: Hasher>(&self, _h: &mut H, hash: u64) -> usize {
+        (hash % self.range as u64) as usize
+    }
+}
+
+/// Daniel Lemire's multiply-and-shift reduction from "A fast
+/// alternative to the modulo reduction". The hash is treated as a
+/// fraction of 2^64 and scaled up to the range, which avoids a
+/// division. Like `Modulo`, some indices are very slightly more likely
+/// when the range doesn't evenly divide 2^64.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lemire {
+    /// The number of indices.
+    range: usize,
+}
+
+impl IndexReducer for Lemire {
+    fn new(range: usize) -> Self {
+        assert!(range > 0);
+
+        Lemire { range }
+    }
+
+    fn range(&self) -> usize {
+        self.range
+    }
+
+    fn reduce<H: Hasher>(&self, _h: &mut H, hash: u64) -> usize {
+        ((hash as u128 * self.range as u128) >> 64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    /// Reduce a synthetic stream of hashes spread evenly across the
+    /// whole `u64` space, returning how many landed on each index.
+    fn histogram<R: IndexReducer>(range: usize, samples: u64) -> Vec<usize> {
+        let reducer = R::new(range);
+        let step = u64::MAX / samples;
+        let mut counts = vec![0; range];
+
+        for i in 0..samples {
+            let mut h: DefaultHasher = Default::default();
+            let ix = reducer.reduce(&mut h, i * step);
+            assert!(ix < range);
+            counts[ix] += 1;
+        }
+
+        counts
+    }
+
+    /// True if every count is within 10% of the mean.
+    fn is_even(counts: &[usize]) -> bool {
+        let mean = counts.iter().sum::<usize>() as f64 / counts.len() as f64;
+        counts
+            .iter()
+            .all(|c| (*c as f64 - mean).abs() < mean * 0.1)
+    }
+
+    #[test]
+    fn rejection_sampling_is_in_range_and_even() {
+        assert!(RejectionSampling::new(1000).mask() == 1023);
+        assert!(is_even(&histogram::<RejectionSampling>(1000, 1000 * 1000)));
+        assert!(is_even(&histogram::<RejectionSampling>(1024, 1024 * 1000)));
+    }
+
+    #[test]
+    fn rejection_sampling_mask_must_cover_the_range() {
+        assert!(RejectionSampling::new(1000).validate().is_ok());
+
+        let narrow = RejectionSampling { range: 1000, mask: 511 };
+        assert!(narrow.validate().is_err());
+    }
+
+    #[test]
+    fn modulo_is_in_range_and_even() {
+        let mut h: DefaultHasher = Default::default();
+        assert!(Modulo::new(10).reduce(&mut h, 1234) == 4);
+        assert!(is_even(&histogram::<Modulo>(1000, 1000 * 1000)));
+    }
+
+    #[test]
+    fn lemire_is_in_range_and_even() {
+        let mut h: DefaultHasher = Default::default();
+        assert!(Lemire::new(10).reduce(&mut h, 0) == 0);
+        assert!(Lemire::new(10).reduce(&mut h, u64::MAX) == 9);
+        assert!(is_even(&histogram::<Lemire>(1000, 1000 * 1000)));
+    }
+}
@@ -4,15 +4,33 @@
 //! Tim Kaler](http://tfk.mit.edu/pdf/bloom.pdf). Their basic
 //! structure, however, is not that compliated.
 
+#[cfg(feature = "mmap")]
+use memmap2::MmapMut;
 use rand::Rng;
 use rand;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use std::any::Any;
+use std::borrow::Borrow;
 use std::fmt;
+#[cfg(feature = "mmap")]
+use std::fs::OpenOptions;
 use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, Read, Write};
+use std::iter::FromIterator;
 use std::marker::PhantomData;
+#[cfg(feature = "mmap")]
+use std::path::Path;
+#[cfg(feature = "stats")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "timing")]
+use std::time::{Duration, Instant};
 use std;
-use bit_array::BitArray;
-use index_mask::index_mask;
-use hash_until::hash_until;
+use bit_array::{BitArray, BitOrder};
+use bloom::{optimal_bits_per_member, optimal_hashers, Saturated};
+use error::BloomError;
+use reducer::{IndexReducer, RejectionSampling};
+use splitmix::{splitmix64, SplitMixHasher};
 
 pub use bloom::BloomFilter;
 
@@ -23,7 +41,7 @@ pub use bloom::BloomFilter;
 ///
 /// let expected_set_size = 1024 * 1024;
 /// let bits_per_item = 16;
-/// let hashing_algos = (bits_per_item as f32 * 0.7).ceil() as usize;
+/// let hashing_algos = (bits_per_item as f64 * 0.7).ceil() as usize;
 ///
 /// let mut dbb: DefaultStandardBloom<usize> = StandardBloom::new(
 ///     expected_set_size,
@@ -34,7 +52,11 @@ pub use bloom::BloomFilter;
 /// dbb.mark(&100);
 /// assert!(dbb.check(&100));
 /// ```
-pub struct StandardBloom<H, T> {
+///
+/// By default, hashes are turned into bit indices with
+/// `RejectionSampling`. A different `IndexReducer` can be chosen with
+/// the `R` parameter.
+pub struct StandardBloom<H, T, R = RejectionSampling> {
     /// The number of hashing functions to use. This also happens to
     /// be the number of bits that will be set in this block for each
     /// item.
@@ -47,8 +69,12 @@ pub struct StandardBloom<H, T> {
     /// The bits in this block.
     bits: BitArray,
 
-    /// A mask to help select a random bit index.
-    mask: u64,
+    /// The number of bits that are set, kept up to date as bits are
+    /// set so `try_mark` doesn't have to count them.
+    ones: usize,
+
+    /// The strategy used to turn a hash into a bit index.
+    reducer: R,
 
     /// The estimated set size.
     n: usize,
@@ -56,31 +82,226 @@ pub struct StandardBloom<H, T> {
     /// The number of bits per member.
     c: usize,
 
+    /// The fill ratio at which `try_mark` stops accepting items.
+    max_fill: Option<f64>,
+
+    /// The total number of hashes the reducer has rejected while
+    /// deriving bit indices. This is atomic so that `check` can stay
+    /// `&self`.
+    #[cfg(feature = "stats")]
+    rejections: AtomicUsize,
+
+    /// The number of `check` calls that found, and didn't find, their
+    /// item.
+    #[cfg(feature = "stats")]
+    hits: AtomicUsize,
+    #[cfg(feature = "stats")]
+    misses: AtomicUsize,
+
     _p_hasher: PhantomData<H>,
     _p_type: PhantomData<T>,
 }
 
+/// Returned from `StandardBloom::check_bounded` when deriving the bit
+/// indices for an item took more hashing than allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashBudgetExceeded;
+
+/// How long `StandardBloom::build_timed` took to mark its items.
+#[cfg(feature = "timing")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildTiming {
+    /// The time taken to mark every item.
+    pub total: Duration,
+
+    /// The part of `total` spent hashing items into bit indices. This
+    /// is an estimate, since timing each item adds overhead of its own.
+    pub hashing: Duration,
+
+    /// The number of items marked.
+    pub items: usize,
+}
+
+/// The number of `check` calls a StandardBloom has answered, from
+/// `StandardBloom::query_stats`.
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryStats {
+    /// Checks that found their item.
+    pub hits: usize,
+
+    /// Checks that didn't find their item.
+    pub misses: usize,
+
+    /// Every check, `hits + misses`.
+    pub queries: usize,
+}
+
+/// The reasons two StandardBloom filters can't be combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeError {
+    /// The filters have a different `n`, `c`, `k`, or number of bits.
+    MismatchedParameters,
+
+    /// The filters hash items with different seeds.
+    MismatchedSeeds,
+
+    /// There were no filters to combine.
+    NoFilters,
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MergeError::MismatchedParameters => write!(f, "the filters have different parameters"),
+            MergeError::MismatchedSeeds => write!(f, "the filters use different seeds"),
+            MergeError::NoFilters => write!(f, "there are no filters to combine"),
+        }
+    }
+}
+
+/// A one-line description of the filter's parameters and how full it
+/// is. Use `Debug` to see the bits themselves.
+impl<H, T, R> fmt::Display for StandardBloom<H, T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "StandardBloom(n={}, c={}, k={}, fill={:.1}%)",
+            self.n,
+            self.c,
+            self.k,
+            self.bits.density() * 100.0
+        )
+    }
+}
+
+/// The number of `u64` values written by `to_bytes` before the bits.
+const HEADER_WORDS: usize = 5;
+
+/// Set in the `k` header word written by `to_bytes` when the filter was
+/// created with `new_pow2`, so `from_bytes` knows to round the number
+/// of bits up.
+const POW2_FLAG: u64 = 1 << 63;
+
+/// Set in the `k` header word written by `to_bytes` when the filter's
+/// bits are stored `MsbFirst`, so `from_bytes` reads them back in the
+/// same order.
+const MSB_FIRST_FLAG: u64 = 1 << 62;
+
+/// The false positive probability targeted by a StandardBloom built with
+/// `collect`.
+pub const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.01;
+
 pub type DefaultStandardBloom<T> = StandardBloom<std::collections::hash_map::DefaultHasher, T>;
 
-impl<H, T> fmt::Debug for StandardBloom<H, T> {
+/// A StandardBloom filter that uses aHash, which is faster than the
+/// DefaultHasher. aHash doesn't promise the same hashes across
+/// versions or machines, so a serialized filter may not be usable
+/// anywhere but where it was created.
+#[cfg(feature = "ahash")]
+pub type AHashStandardBloom<T> = StandardBloom<::ahash::AHasher, T>;
+
+impl<H, T, R> fmt::Debug for StandardBloom<H, T, R> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "StandardBloom {{ bits: {:?} }}", self.bits)
     }
 }
 
-impl<H: Hasher + Default, T: Hash> BloomFilter<T> for StandardBloom<H, T> {
+/// Two StandardBloom filters are equal when they have the same
+/// parameters, the same seeds, and the same bits set.
+impl<H, T, R> PartialEq for StandardBloom<H, T, R> {
+    fn eq(&self, other: &Self) -> bool {
+        self.n == other.n && self.c == other.c && self.k == other.k &&
+            self.seed1 == other.seed1 && self.seed2 == other.seed2 &&
+            self.bits == other.bits
+    }
+}
+
+impl<H, T, R> Eq for StandardBloom<H, T, R> {}
+
+/// Hashing a StandardBloom filter hashes its parameters, its seeds,
+/// and the entire bit array, so it's O(m) in the number of bits.
+impl<H, T, R> Hash for StandardBloom<H, T, R> {
+    fn hash<S: Hasher>(&self, state: &mut S) {
+        self.n.hash(state);
+        self.c.hash(state);
+        self.k.hash(state);
+        self.seed1.hash(state);
+        self.seed2.hash(state);
+        self.bits.hash(state);
+    }
+}
+
+/// Cloning copies the bits onto the heap, even for a filter created
+/// with `open_mmap`.
+impl<H, T, R: Clone> Clone for StandardBloom<H, T, R> {
+    fn clone(&self) -> Self {
+        StandardBloom {
+            k: self.k,
+            seed1: self.seed1,
+            seed2: self.seed2,
+            bits: self.bits.clone(),
+            ones: self.ones,
+            reducer: self.reducer.clone(),
+            n: self.n,
+            c: self.c,
+            max_fill: self.max_fill,
+            #[cfg(feature = "stats")]
+            rejections: AtomicUsize::new(self.rejections.load(Ordering::Relaxed)),
+            #[cfg(feature = "stats")]
+            hits: AtomicUsize::new(self.hits.load(Ordering::Relaxed)),
+            #[cfg(feature = "stats")]
+            misses: AtomicUsize::new(self.misses.load(Ordering::Relaxed)),
+
+            _p_hasher: PhantomData,
+            _p_type: PhantomData,
+        }
+    }
+}
+
+impl<H, T, R> BloomFilter<T> for StandardBloom<H, T, R>
+where
+    H: Hasher + Default,
+    T: Hash,
+    R: IndexReducer,
+{
     fn name(&self) -> &str {
         "standard"
     }
 
     fn mark(&mut self, item: &T) {
-        for ix in self.hash(item) {
-            self.bits.set(ix);
+        for ix in self.bit_indices(item) {
+            self.set_bit(ix);
         }
     }
 
     fn check(&self, item: &T) -> bool {
-        self.hash(item).iter().all(|ix| self.bits.get(*ix))
+        let found = self.bit_indices(item).iter().all(|ix| self.bits.get(*ix));
+
+        #[cfg(feature = "stats")]
+        {
+            let counter = if found { &self.hits } else { &self.misses };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+
+        found
+    }
+
+    fn clear(&mut self) {
+        self.bits.clear_all();
+        self.ones = 0;
+    }
+
+    /// Fails once the fill ratio reaches the limit set with
+    /// `set_max_fill`.
+    fn try_mark(&mut self, item: &T) -> Result<(), Saturated> {
+        match self.max_fill {
+            Some(max) if self.ones as f64 >= max * self.bits.width() as f64 => Err(Saturated),
+            _ => {
+                self.mark(item);
+                Ok(())
+            }
+        }
     }
 
     fn set_size(&self) -> usize {
@@ -94,9 +315,39 @@ impl<H: Hasher + Default, T: Hash> BloomFilter<T> for StandardBloom<H, T> {
     fn hash_count(&self) -> usize {
         self.k
     }
+
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self.bits.as_words())
+    }
+
+    fn as_any(&self) -> &dyn Any
+    where
+        Self: 'static,
+    {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any
+    where
+        Self: 'static,
+    {
+        self
+    }
+
+    fn box_clone(&self) -> Box<dyn BloomFilter<T>>
+    where
+        Self: 'static,
+    {
+        Box::new(self.clone())
+    }
 }
 
-impl<H: Hasher + Default, T: Hash> StandardBloom<H, T> {
+impl<H, T, R> StandardBloom<H, T, R>
+where
+    H: Hasher + Default,
+    T: Hash,
+    R: IndexReducer,
+{
     /// Create a new StandardBloom filter that with an approximate set
     /// size of `n`, uses `c` bits per member, and `k` hashing
     /// functions.
@@ -105,6 +356,58 @@ impl<H: Hasher + Default, T: Hash> StandardBloom<H, T> {
         StandardBloom::new_with_seeds(n, c, k, rng.gen::<u64>(), rng.gen::<u64>())
     }
 
+    /// Create a filter like `new` and mark every item from `items` in
+    /// it, timing how long marking took and how much of that was spent
+    /// hashing. This is meant for benchmarks and examples.
+    #[cfg(feature = "timing")]
+    pub fn build_timed<I>(n: usize, c: usize, k: usize, items: I) -> (Self, BuildTiming)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut bb = StandardBloom::new(n, c, k);
+        let mut hashing = Duration::default();
+        let mut indices = Vec::with_capacity(k);
+        let mut count = 0;
+
+        let start = Instant::now();
+        for item in items {
+            let hash_start = Instant::now();
+            indices.clear();
+            bb.hash_into(&item, &mut indices);
+            hashing += hash_start.elapsed();
+
+            for ix in &indices {
+                bb.set_bit(*ix);
+            }
+            count += 1;
+        }
+
+        let timing = BuildTiming {
+            total: start.elapsed(),
+            hashing,
+            items: count,
+        };
+        (bb, timing)
+    }
+
+    /// Like `new`, but derives the number of hashing functions from
+    /// `c` using `optimal_hashers`.
+    pub fn new_auto_k(n: usize, c: usize) -> Self {
+        StandardBloom::new(n, c, optimal_hashers(c).min(c))
+    }
+
+    /// Create a filter sized for exactly the items in `items` with a
+    /// false positive probability of about `target_fp`, and mark every
+    /// item in it.
+    pub fn built_from(items: &[T], target_fp: f64) -> Self {
+        let c = optimal_bits_per_member(target_fp);
+        let mut bb = StandardBloom::new_auto_k(items.len().max(1), c);
+        for item in items {
+            bb.mark(item);
+        }
+        bb
+    }
+
     /// Like `new`, but allows the specification of the seeds to use
     /// for the hashers.
     pub fn new_with_seeds(
@@ -113,100 +416,1745 @@ impl<H: Hasher + Default, T: Hash> StandardBloom<H, T> {
         k: usize,
         seed1: u64,
         seed2: u64,
-    ) -> StandardBloom<H, T> {
+    ) -> StandardBloom<H, T, R> {
+        assert!(k <= c);
+        StandardBloom::new_with_bit_array(n, c, k, BitArray::new(n * c), seed1, seed2)
+    }
+
+    /// Create a filter of `total_bits` bits that uses `k` hashers, with
+    /// no relationship between the two. This is meant for experimenting
+    /// with `k` independently of the size of the filter, so `k` may
+    /// even exceed `total_bits`, in which case some hashers are bound
+    /// to set the same bits.
+    ///
+    /// The filter is treated as holding a single member of `total_bits`
+    /// bits, so `set_size` is 1 and `bits_per_member` is `total_bits`.
+    pub fn new_decoupled(total_bits: usize, k: usize, seed1: u64, seed2: u64) -> Self {
+        StandardBloom::new_with_bit_array(1, total_bits, k, BitArray::new(total_bits), seed1, seed2)
+    }
+
+    /// Like `new`, but rounds the number of bits up to the next power of
+    /// two. Every masked hash is then a valid bit index, so
+    /// `RejectionSampling` never has to rehash. This trades up to twice
+    /// the memory for a fixed amount of hashing per item.
+    pub fn new_pow2(n: usize, c: usize, k: usize) -> Self {
+        let mut rng = rand::thread_rng();
+        StandardBloom::new_pow2_with_seeds(n, c, k, rng.gen::<u64>(), rng.gen::<u64>())
+    }
+
+    /// Like `new_pow2`, but allows the specification of the seeds to
+    /// use for the hashers.
+    pub fn new_pow2_with_seeds(
+        n: usize,
+        c: usize,
+        k: usize,
+        seed1: u64,
+        seed2: u64,
+    ) -> StandardBloom<H, T, R> {
+        assert!(k <= c);
+        let bits = BitArray::new((n * c).next_power_of_two());
+        StandardBloom::new_with_bit_array(n, c, k, bits, seed1, seed2)
+    }
+
+    /// Create a filter that uses `bits` rather than allocating its own,
+    /// so BitArrays can be reused. Any bits already set in `bits` are
+    /// kept.
+    ///
+    /// Only the width of `bits` is known, so `n` and `c` are made up to
+    /// fit it: `c` is the smallest divisor of the width that is at least
+    /// the number of bits per member `k` is optimal for, and `n` is the
+    /// width divided by `c`.
+    pub fn from_bit_array(bits: BitArray, k: usize, seed1: u64, seed2: u64) -> Self {
         assert!(k > 0);
-        assert!(n * c > 0);
+        assert!(k <= bits.width());
+
+        let width = bits.width();
+        let min_c = (k as f64 / std::f64::consts::LN_2).ceil() as usize;
+        let c = (min_c..width + 1).find(|c| width % *c == 0).unwrap_or(width);
+
+        StandardBloom::new_with_bit_array(width / c, c, k, bits, seed1, seed2)
+    }
+
+    /// Create a filter whose bits are stored in the file at `path`
+    /// through a memory map, leaving the operating system to decide
+    /// which parts are kept in memory. A missing or empty file is
+    /// created with every bit clear. Otherwise the file must be the
+    /// size a filter of `n * c` bits needs, and its bits are used as
+    /// they are.
+    ///
+    /// Only the bits are stored in the file, so the same `n`, `c`, `k`,
+    /// and seeds must be given every time it's opened. The bits are
+    /// stored in the machine's byte order. Call `flush` to make sure
+    /// changes have been written to the file.
+    #[cfg(feature = "mmap")]
+    pub fn open_mmap<P: AsRef<Path>>(
+        path: P,
+        n: usize,
+        c: usize,
+        k: usize,
+        seed1: u64,
+        seed2: u64,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        if file.metadata()?.len() == 0 {
+            file.set_len(((n * c).div_ceil(64) * 8) as u64)?;
+        }
 
+        // The map is only sound while no other process resizes or
+        // writes the file, which callers have to ensure.
+        let map = unsafe { MmapMut::map_mut(&file)? };
         assert!(k <= c);
+        let bits = BitArray::from_mmap(n * c, map)?;
+
+        Ok(StandardBloom::new_with_bit_array(n, c, k, bits, seed1, seed2))
+    }
+
+    /// Write any changes to the bits of a filter created with
+    /// `open_mmap` back to its file. This does nothing for any other
+    /// filter.
+    #[cfg(feature = "mmap")]
+    pub fn flush(&self) -> io::Result<()> {
+        self.bits.flush()
+    }
+
+    /// Create a filter that uses `bits` as its bits, which may be wider
+    /// than `n * c`. Any bits already set in `bits` are kept. `k` isn't
+    /// checked against `c`, so that `new_decoupled` can use any `k`;
+    /// the other constructors check it themselves.
+    fn new_with_bit_array(
+        n: usize,
+        c: usize,
+        k: usize,
+        bits: BitArray,
+        seed1: u64,
+        seed2: u64,
+    ) -> StandardBloom<H, T, R> {
+        assert!(k > 0);
+        assert!(n * c > 0);
+        assert!(bits.width() >= n * c);
 
-        let bits = n * c;
+        let reducer = R::new(bits.width());
+        let ones = bits.count_ones();
 
-        let max_bit_index = bits - 1;
         StandardBloom {
-            n: n,
-            c: c,
-            k: k,
+            n,
+            c,
+            k,
 
-            seed1: seed1,
-            seed2: seed2,
+            seed1,
+            seed2,
 
-            bits: BitArray::new(bits),
-            mask: index_mask(max_bit_index as u64),
+            bits,
+            ones,
+            reducer,
+            max_fill: None,
+            #[cfg(feature = "stats")]
+            rejections: AtomicUsize::new(0),
+            #[cfg(feature = "stats")]
+            hits: AtomicUsize::new(0),
+            #[cfg(feature = "stats")]
+            misses: AtomicUsize::new(0),
 
             _p_hasher: PhantomData,
             _p_type: PhantomData,
         }
     }
 
+    /// Build a new filter with different parameters but the same
+    /// seeds, marking every item from `items` in it. The bits of a
+    /// bloom filter can't be moved into a larger filter, so the only
+    /// way to grow one is to insert the original items again. `items`
+    /// should contain every item that was marked in this filter.
+    pub fn resize_from<I>(&self, new_n: usize, new_c: usize, new_k: usize, items: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut resized =
+            StandardBloom::new_with_seeds(new_n, new_c, new_k, self.seed1, self.seed2);
+        for item in items {
+            resized.mark(&item);
+        }
+        resized
+    }
+
+    /// Replace the filter with an empty one using new parameters. Unlike
+    /// `clear`, the bits are reallocated to fit `n * c`. The seeds and
+    /// the `try_mark` limit are kept.
+    pub fn reinit(&mut self, n: usize, c: usize, k: usize) {
+        assert!(k > 0);
+        assert!(n * c > 0);
+
+        assert!(k <= c);
+
+        self.n = n;
+        self.c = c;
+        self.k = k;
+        self.bits = BitArray::new_with_order(n * c, self.bits.order());
+        self.ones = 0;
+        self.reducer = R::new(n * c);
+    }
+
+    /// Clear every bit and hash items with `seed1` and `seed2` from now
+    /// on, keeping the bits' allocation. The filter then behaves like a
+    /// new one created by `new_with_seeds` with the same parameters.
+    /// `clear` is the same but keeps the current seeds.
+    pub fn reset_to_seeds(&mut self, seed1: u64, seed2: u64) {
+        self.bits.clear_all();
+        self.ones = 0;
+        self.seed1 = seed1;
+        self.seed2 = seed2;
+    }
+
+    /// Check the filter's internal invariants, returning a description
+    /// of the first one that doesn't hold. A filter built by this crate
+    /// always validates; this is meant for catching corruption, such as
+    /// after reading a filter back with `from_bytes`.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.k == 0 {
+            return Err("k is zero");
+        }
+        // Only `new_decoupled` makes filters with `k > c`, and they
+        // always hold a single member.
+        if self.k > self.c && self.n > 1 {
+            return Err("k is larger than c");
+        }
+        if self.n.checked_mul(self.c).map_or(true, |m| m == 0) {
+            return Err("n * c is zero or overflows");
+        }
+        if self.bits.width() < self.n * self.c {
+            return Err("there are fewer bits than n * c");
+        }
+        let (width, order) = (self.bits.width(), self.bits.order());
+        if !BitArray::words_fit_with_order(width, self.bits.as_words(), order) {
+            return Err("the backing words don't match the number of bits");
+        }
+        if self.reducer.range() != self.bits.width() {
+            return Err("the reducer's range doesn't match the number of bits");
+        }
+        if self.ones != self.bits.count_ones() {
+            return Err("the count of set bits is out of date");
+        }
+        self.reducer.validate()
+    }
+
+    /// Set the fill ratio at which `try_mark` stops accepting items, or
+    /// `None` to accept items no matter how full the filter is.
+    pub fn set_max_fill(&mut self, max_fill: Option<f64>) {
+        self.max_fill = max_fill;
+    }
+
+    /// The total number of hashes rejected while turning hashes into bit
+    /// indices, across every `mark` and `check` so far. Rejections only
+    /// happen with `RejectionSampling` when the number of bits isn't a
+    /// power of two; a high count means `index_mask` is a poor fit.
+    #[cfg(feature = "stats")]
+    pub fn rejection_count(&self) -> usize {
+        self.rejections.load(Ordering::Relaxed)
+    }
+
+    /// Add `rejections` to the count returned by `rejection_count`.
+    /// Without the `stats` feature this does nothing, so `mark` and
+    /// `check` make no shared writes.
+    #[inline]
+    fn count_rejections(&self, _rejections: usize) {
+        #[cfg(feature = "stats")]
+        {
+            if _rejections > 0 {
+                self.rejections.fetch_add(_rejections, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// How many `check` calls have found, and not found, their item
+    /// over the life of the filter. `clear` doesn't reset the counts.
+    #[cfg(feature = "stats")]
+    pub fn query_stats(&self) -> QueryStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+
+        QueryStats {
+            hits,
+            misses,
+            queries: hits + misses,
+        }
+    }
+
+    /// The number of hashing functions that gives the lowest false
+    /// positive rate for the number of bits per member in the filter.
+    pub fn suggested_k(&self) -> usize {
+        optimal_hashers(self.c)
+    }
+
+    /// The number of hashing functions that would give the lowest false
+    /// positive rate for the number of members the filter appears to
+    /// hold, judging by its fill ratio. This is only advice: `k` can't
+    /// change once items are marked. A value well below `hash_count`
+    /// means the filter holds more than it was sized for.
+    ///
+    /// With `k` hashes, a fill ratio of `f` suggests about
+    /// `-ln(1 - f) * m / k` members in `m` bits, and the best `k` for
+    /// that many members is `m / members * ln(2)`. An empty filter gives
+    /// infinity and a full one gives zero.
+    pub fn effective_k_for_current_fill(&self) -> f64 {
+        let empty = 1.0 - self.fill_ratio();
+        self.k as f64 * std::f64::consts::LN_2 / (1.0 / empty).ln()
+    }
+
+    /// True if the filter uses the suggested number of hashing
+    /// functions for its number of bits per member.
+    pub fn parameters_are_optimal(&self) -> bool {
+        self.k == self.suggested_k()
+    }
+
+    /// The largest number of members the filter can hold, for its
+    /// number of bits `m` and hashers `k`, before the predicted false
+    /// positive probability exceeds `target_fp`. This inverts
+    /// `(1 - e^(-kn/m))^k` to give `n = -m/k * ln(1 - target_fp^(1/k))`.
+    pub fn capacity_at_fp(&self, target_fp: f64) -> usize {
+        assert!(target_fp > 0.0 && target_fp < 1.0);
+
+        let m = self.bits.width() as f64;
+        let k = self.k as f64;
+        (-m / k * (1.0 - target_fp.powf(1.0 / k)).ln()).floor() as usize
+    }
+
+    /// The words backing the filter's bits as bytes, borrowed without
+    /// copying. Only the bits are included, and the words are in the
+    /// machine's byte order, so these bytes can't be read back on a
+    /// machine of the other endianness. Use `to_bytes` for portable
+    /// serialization.
+    pub fn backing_bytes(&self) -> &[u8] {
+        let words = self.bits.as_words();
+
+        // Every u64 is made of 8 bytes, and bytes need no alignment.
+        unsafe { std::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 8) }
+    }
+
+    /// Serialize the filter to bytes. The parameters, seeds, and bits
+    /// are all written as little-endian `u64` values, so the bytes can
+    /// be read back by `from_bytes` on any platform. The same hasher
+    /// type must be used to read the filter back.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let words = self.bits.as_words();
+        let mut bytes = Vec::with_capacity((HEADER_WORDS + words.len()) * 8);
+
+        let mut k = self.k as u64;
+        if self.bits.width() != self.n * self.c {
+            k |= POW2_FLAG;
+        }
+        if self.bits.order() == BitOrder::MsbFirst {
+            k |= MSB_FIRST_FLAG;
+        }
+
+        for v in &[self.n as u64, self.c as u64, k, self.seed1, self.seed2] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+
+        for w in words {
+            bytes.extend_from_slice(&w.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    /// Deserialize a filter written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BloomError> {
+        if bytes.len() % 8 != 0 {
+            return Err(BloomError::Deserialize("length is not a multiple of 8"));
+        }
+        if bytes.len() < HEADER_WORDS * 8 {
+            return Err(BloomError::Deserialize("too short to hold a header"));
+        }
+
+        let mut values = bytes.chunks(8).map(|chunk| {
+            let mut word = [0; 8];
+            word.copy_from_slice(chunk);
+            u64::from_le_bytes(word)
+        });
+
+        let header: Vec<u64> = values.by_ref().take(HEADER_WORDS).collect();
+        let (n, c) = (header[0] as usize, header[1] as usize);
+        let k = (header[2] & !(POW2_FLAG | MSB_FIRST_FLAG)) as usize;
+        let pow2 = header[2] & POW2_FLAG != 0;
+        let order = if header[2] & MSB_FIRST_FLAG != 0 {
+            BitOrder::MsbFirst
+        } else {
+            BitOrder::LsbFirst
+        };
+        let (seed1, seed2) = (header[3], header[4]);
+        let words: Vec<u64> = values.collect();
+
+        let mut bits = n.checked_mul(c).ok_or(BloomError::Overflow)?;
+        if pow2 {
+            bits = bits.checked_next_power_of_two().ok_or(BloomError::Overflow)?;
+        }
+        // As in `validate`, only a decoupled filter may have `k > c`.
+        if k == 0 || (k > c && n > 1) {
+            return Err(BloomError::InvalidParameters("k must be between 1 and c"));
+        }
+        if !BitArray::words_fit_with_order(bits, &words, order) {
+            return Err(BloomError::Deserialize("bits don't match the parameters"));
+        }
+
+        let bits = BitArray::from_words_with_order(bits, words, order);
+        Ok(StandardBloom::new_with_bit_array(n, c, k, bits, seed1, seed2))
+    }
+
+    /// Write the filter to `w` as `to_bytes` prefixed with its length
+    /// as a 4-byte big-endian number, so several filters can be sent
+    /// one after another over a stream and read back with
+    /// `read_framed`.
+    pub fn write_framed<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let bytes = self.to_bytes();
+        if bytes.len() > u32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the filter is too large for a 4-byte length prefix",
+            ));
+        }
+
+        w.write_all(&(bytes.len() as u32).to_be_bytes())?;
+        w.write_all(&bytes)
+    }
+
+    /// Read a filter written by `write_framed` from `r`, consuming only
+    /// its length prefix and bytes.
+    pub fn read_framed<Rd: Read>(r: &mut Rd) -> io::Result<Self> {
+        let mut prefix = [0; 4];
+        r.read_exact(&mut prefix)?;
+
+        // The prefix can't be trusted, so only allocate for the bytes
+        // that actually arrive rather than for the length it claims.
+        let len = u32::from_be_bytes(prefix) as u64;
+        let mut bytes = Vec::new();
+        r.take(len).read_to_end(&mut bytes)?;
+        if (bytes.len() as u64) < len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated filter"));
+        }
+
+        StandardBloom::from_bytes(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Count the bits that are set in both this filter and `other`
+    /// without allocating a new filter. The filters must have the same
+    /// parameters and seeds.
+    pub fn intersection_ones(&self, other: &Self) -> Result<usize, MergeError> {
+        self.check_mergeable(other)?;
+
+        Ok(self.bits
+            .as_words()
+            .iter()
+            .zip(other.bits.as_words())
+            .map(|(a, b)| (a & b).count_ones() as usize)
+            .sum())
+    }
+
+    /// Add every member of `other` to this filter. The filters must
+    /// have the same parameters and seeds.
+    pub fn union(&mut self, other: &Self) -> Result<(), MergeError> {
+        self.check_mergeable(other)?;
+        self.bits.union(&other.bits);
+        self.ones = self.bits.count_ones();
+        Ok(())
+    }
+
+    /// Add every member of `other` to this filter like `union`, or, if
+    /// the filters can't be merged, by marking every item in
+    /// `other_items_fallback` instead. The fallback should hold every
+    /// item marked in `other`. Returns true if the bits were merged and
+    /// false if the items were marked again.
+    pub fn union_or_rebuild<I>(&mut self, other: &Self, other_items_fallback: I) -> bool
+    where
+        I: IntoIterator<Item = T>,
+    {
+        if self.union(other).is_ok() {
+            return true;
+        }
+
+        for item in other_items_fallback {
+            self.mark(&item);
+        }
+        false
+    }
+
+    /// Create a new filter holding the members of both `a` and `b`,
+    /// leaving them unchanged. The filters must have the same
+    /// parameters and seeds.
+    pub fn merged(a: &Self, b: &Self) -> Result<Self, MergeError> {
+        a.check_mergeable(b)?;
+
+        let mut bits = BitArray::new_with_order(a.bits.width(), a.bits.order());
+        bits.union(&a.bits);
+        bits.union(&b.bits);
+
+        Ok(StandardBloom::new_with_bit_array(a.n, a.c, a.k, bits, a.seed1, a.seed2))
+    }
+
+    /// Create a new filter holding the members of every filter in
+    /// `filters`. The filters must all have the same parameters and
+    /// seeds.
+    pub fn union_all(filters: &[Self]) -> Result<Self, MergeError> {
+        let first = filters.first().ok_or(MergeError::NoFilters)?;
+        let mut combined = StandardBloom::new_with_bit_array(
+            first.n,
+            first.c,
+            first.k,
+            BitArray::new_with_order(first.bits.width(), first.bits.order()),
+            first.seed1,
+            first.seed2,
+        );
+
+        for filter in filters {
+            combined.union(filter)?;
+        }
+
+        Ok(combined)
+    }
+
+    /// True if this filter and `other` can be combined with `union`,
+    /// `merged`, or `intersection_ones`: they have the same
+    /// parameters, number of bits, bit order, and seeds.
+    pub fn is_compatible_with(&self, other: &Self) -> bool {
+        self.check_mergeable(other).is_ok()
+    }
+
+    /// Ensure that `other` sets the same bits for an item as this
+    /// filter does, so their bits can be combined.
+    fn check_mergeable(&self, other: &Self) -> Result<(), MergeError> {
+        if self.n != other.n || self.c != other.c || self.k != other.k ||
+            self.bits.width() != other.bits.width() || self.bits.order() != other.bits.order()
+        {
+            Err(MergeError::MismatchedParameters)
+        } else if self.seed1 != other.seed1 || self.seed2 != other.seed2 {
+            Err(MergeError::MismatchedSeeds)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Estimate the false positive rate by checking `samples` items made
+    /// from random `u64`s, returning the fraction that the filter
+    /// claims are members. The probes are assumed not to be members;
+    /// any that actually are count as false positives, so this is an
+    /// upper bound on the true rate.
+    pub fn measure_fp_rate<G: Rng>(&self, rng: &mut G, samples: usize) -> f64
+    where
+        T: From<u64>,
+    {
+        assert!(samples > 0);
+
+        let hits = (0..samples)
+            .filter(|_| self.check(&T::from(rng.gen::<u64>())))
+            .count();
+
+        hits as f64 / samples as f64
+    }
+
+    /// The fraction of bits in the filter that are set.
+    pub fn fill_ratio(&self) -> f64 {
+        self.bits.density()
+    }
+
+    /// Draw the bits as a grid `width` columns wide, with `█` for a set
+    /// bit and `·` for a clear one. Every row, including the last,
+    /// ends with a newline. This is meant for looking at small filters
+    /// in docs and while debugging; a large filter makes a very large
+    /// string.
+    pub fn ascii_art(&self, width: usize) -> String {
+        assert!(width > 0);
+
+        let mut art = String::new();
+        for (ix, set) in self.bits.bits().enumerate() {
+            art.push(if set { '█' } else { '·' });
+            if (ix + 1) % width == 0 || ix + 1 == self.bits.width() {
+                art.push('\n');
+            }
+        }
+        art
+    }
+
+    /// Count how many of the `k` bits for `item` are set. When this
+    /// is equal to `k`, `check` will be true for `item`.
+    pub fn matching_bits(&self, item: &T) -> usize {
+        self.bit_indices(item)
+            .iter()
+            .filter(|ix| self.bits.get(**ix))
+            .count()
+    }
+
     /// Create a list of bit indicies representing the bloom filter
     /// hash for `item`.
-    fn hash(&self, item: &T) -> Vec<usize> {
-        let mut h1: H = Default::default();
-        let mut h2: H = Default::default();
-        h1.write_u64(self.seed1);
-        h2.write_u64(self.seed2);
+    pub fn bit_indices(&self, item: &T) -> Vec<usize> {
+        let mut v = Vec::with_capacity(self.k);
+        self.hash_into(item, &mut v);
+        v
+    }
 
-        item.hash(&mut h1);
-        item.hash(&mut h2);
+    /// The number of distinct indices among the `k` bit indices for
+    /// `item`. Indices can repeat, in which case `mark` sets fewer than
+    /// `k` bits; if this is often below `k`, the filter has too few
+    /// bits.
+    pub fn distinct_indices(&self, item: &T) -> usize {
+        let mut indices = self.bit_indices(item);
+        indices.sort_unstable();
+        indices.dedup();
+        indices.len()
+    }
 
-        let ih1 = h1.finish();
-        let ih2 = h2.finish();
+    /// Mark `item`, returning how many of its bits were not already
+    /// set. Zero means `check` was already true for `item`.
+    pub fn mark_reporting_new(&mut self, item: &T) -> usize {
+        self.bit_indices(item)
+            .into_iter()
+            .filter(|ix| self.set_bit(*ix))
+            .count()
+    }
 
-        let mut v = vec![0; self.k];
-        for i in 0..self.k {
-            // A. Kirsch and M. Mitzenmacher describe a way to
-            // generate multiple hashes without having to recompute
-            // every time in their paper "Less Hashing, Same
-            // Performance: Building a Better Bloom Filter" published
-            // September 2008. It's generalized below as:
-            //
-            //    hi = h1 + (i * h2)
-            //
-            // Their paper identifies that this mechanism allows us to
-            // calculate two hashes once, and derive any number of
-            // hashes from those initial two without losing entropy in
-            // each successive hash.
-            //
-            // We generate this k_and_m hash and then test whether or
-            // not it's a suitable candidate for producing a random
-            // bit index. In order to treat all indicies fairly, the
-            // hash is recalculated until masking off the top bits of
-            // the hash produces a number that's less than or equal to
-            // the number of bits in the block.
-
-            // The value for the i'th hash.
-            let k_and_m = ih1.wrapping_add((i as u64).wrapping_mul(ih2));
-
-            // The hasher used for looping.
-            let mut h3: H = Default::default();
+    /// Set bit `ix`, returning true if it wasn't already set.
+    fn set_bit(&mut self, ix: usize) -> bool {
+        let newly_set = !self.bits.set_returning_previous(ix);
+        if newly_set {
+            self.ones += 1;
+        }
+        newly_set
+    }
+
+    /// The bit indices for every item in `items`, in the same order.
+    pub fn indices_for_many(&self, items: &[T]) -> Vec<Vec<usize>> {
+        items.iter().map(|item| self.bit_indices(item)).collect()
+    }
 
-            // This will be true when the hash can be used to produce
-            // a random bit index.
-            let prop = |h| (self.mask & h) <= (self.bits.width() - 1) as u64;
+    /// Like `bit_indices`, but the indices replace the contents of
+    /// `out`. Reusing `out` for many items avoids allocating a new list
+    /// for each one.
+    pub fn hash_into(&self, item: &T, out: &mut Vec<usize>) {
+        let (ih1, ih2) = self.base_hashes(item);
+        self.bit_indices_from_hashes_into(ih1, ih2, out);
+    }
+
+    /// Calculate the two base hashes for `item` that all `k` bit
+    /// indices are derived from. These can be passed to
+    /// `mark_with_hash` and `check_with_hash`.
+    pub fn base_hashes(&self, item: &T) -> (u64, u64) {
+        self.base_hashes_of(item)
+    }
+
+    /// Mark a borrowed form of an item, such as a `&str` for a filter
+    /// of `String`s, without creating an owned `T`.
+    pub fn mark_borrowed<Q: Hash + ?Sized>(&mut self, item: &Q)
+    where
+        T: Borrow<Q>,
+    {
+        let (h1, h2) = self.base_hashes_of(item);
+        self.mark_with_hash(h1, h2);
+    }
+
+    /// Check a borrowed form of an item, such as a `&str` for a filter
+    /// of `String`s, without creating an owned `T`.
+    pub fn check_borrowed<Q: Hash + ?Sized>(&self, item: &Q) -> bool
+    where
+        T: Borrow<Q>,
+    {
+        let (h1, h2) = self.base_hashes_of(item);
+        self.check_with_hash(h1, h2)
+    }
 
-            // This hash, when masked, will give us a usable bit
-            // index.
-            let usable_hash = hash_until(&mut h3, k_and_m, prop);
+    /// Mark every line read from `reader`, with surrounding whitespace
+    /// trimmed, and return how many were marked. Blank lines are
+    /// skipped. Lines are read one at a time, so the input doesn't need
+    /// to fit in memory.
+    pub fn mark_lines<B: BufRead>(&mut self, mut reader: B) -> io::Result<usize>
+    where
+        T: Borrow<str>,
+    {
+        let mut line = String::new();
+        let mut marked = 0;
 
-            // Store the bit index into the vector.
-            v[i] = (self.mask & usable_hash) as usize;
+        while reader.read_line(&mut line)? > 0 {
+            let key = line.trim();
+            if !key.is_empty() {
+                self.mark_borrowed(key);
+                marked += 1;
+            }
+            line.clear();
         }
 
-        v
+        Ok(marked)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use bloom::optimal_hashers;
-    use super::*;
+    /// Mark every item from every one of `sources`, such as the shards
+    /// of a sharded input.
+    pub fn mark_from_sources<I, J>(&mut self, sources: I)
+    where
+        I: IntoIterator<Item = J>,
+        J: IntoIterator<Item = T>,
+    {
+        for item in sources.into_iter().flatten() {
+            self.mark(&item);
+        }
+    }
 
-    #[test]
-    fn the_basics_work() {
+    /// Calculate the two base hashes for anything hashed the same way
+    /// as `T`.
+    fn base_hashes_of<Q: Hash + ?Sized>(&self, item: &Q) -> (u64, u64) {
+        let mut h1: H = Default::default();
+        let mut h2: H = Default::default();
+        h1.write_u64(self.seed1);
+        h2.write_u64(self.seed2);
+
+        item.hash(&mut h1);
+        item.hash(&mut h2);
+
+        (h1.finish(), h2.finish())
+    }
+
+    /// Set the bits derived from the base hashes `h1` and `h2`. This
+    /// allows items hashed by some other means to be marked without
+    /// hashing them again.
+    pub fn mark_with_hash(&mut self, h1: u64, h2: u64) {
+        for ix in self.bit_indices_from_hashes(h1, h2) {
+            self.set_bit(ix);
+        }
+    }
+
+    /// True if the bits derived from the base hashes `h1` and `h2` are
+    /// all set.
+    pub fn check_with_hash(&self, h1: u64, h2: u64) -> bool {
+        self.bit_indices_from_hashes(h1, h2)
+            .iter()
+            .all(|ix| self.bits.get(*ix))
+    }
+
+    /// Set the bits derived from a single 128-bit `digest` of an item.
+    /// The low 64 bits are used as `h1` and the high 64 bits as `h2`,
+    /// as in `mark_with_hash`. A `[u8; 16]` digest can be converted
+    /// with `u128::from_le_bytes`.
+    pub fn mark_digest(&mut self, digest: u128) {
+        let (h1, h2) = split_digest(digest);
+        self.mark_with_hash(h1, h2);
+    }
+
+    /// True if the bits derived from the 128-bit `digest` are all set.
+    /// The digest is split as in `mark_digest`.
+    pub fn check_digest(&self, digest: u128) -> bool {
+        let (h1, h2) = split_digest(digest);
+        self.check_with_hash(h1, h2)
+    }
+
+    /// Like `check`, but gives up with `HashBudgetExceeded` if more than
+    /// `max_hash_iters` hashes are rejected while deriving the bit
+    /// indices for `item`, counted across all `k` of them. This bounds
+    /// the work a query can cause when items are chosen by someone
+    /// trying to make the reducer rehash over and over.
+    pub fn check_bounded(
+        &self,
+        item: &T,
+        max_hash_iters: usize,
+    ) -> Result<bool, HashBudgetExceeded> {
+        let (h1, h2) = self.base_hashes(item);
+        let mut used = 0;
+
+        for i in 0..self.k {
+            let mut h3: H = Default::default();
+            let hash = h1.wrapping_add((i as u64).wrapping_mul(h2));
+            let (ix, rejections) = self.reducer
+                .reduce_bounded(&mut h3, hash, max_hash_iters - used)
+                .ok_or(HashBudgetExceeded)?;
+
+            used += rejections;
+            self.count_rejections(rejections);
+
+            if !self.bits.get(ix) {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Create a list of bit indicies from the two base hashes of an
+    /// item.
+    fn bit_indices_from_hashes(&self, ih1: u64, ih2: u64) -> Vec<usize> {
+        let mut v = Vec::with_capacity(self.k);
+        self.bit_indices_from_hashes_into(ih1, ih2, &mut v);
+        v
+    }
+
+    /// Replace the contents of `out` with the bit indices derived from
+    /// the two base hashes of an item.
+    fn bit_indices_from_hashes_into(&self, ih1: u64, ih2: u64, out: &mut Vec<usize>) {
+        out.clear();
+        out.extend((0..self.k).map(|i| self.bit_index::<H>(ih1, ih2, i)));
+    }
+
+    /// The `i`th bit index derived from the two base hashes of an item.
+    /// Rejected hashes are rehashed with `G`.
+    fn bit_index<G: Hasher + Default>(&self, ih1: u64, ih2: u64, i: usize) -> usize {
+        // A. Kirsch and M. Mitzenmacher describe a way to generate
+        // multiple hashes without having to recompute every time in
+        // their paper "Less Hashing, Same Performance: Building a
+        // Better Bloom Filter" published September 2008. It's
+        // generalized below as:
+        //
+        //    hi = h1 + (i * h2)
+        //
+        // Their paper identifies that this mechanism allows us to
+        // calculate two hashes once, and derive any number of hashes
+        // from those initial two without losing entropy in each
+        // successive hash.
+        //
+        // We generate this k_and_m hash and then let the reducer turn
+        // it into a bit index.
+
+        // The value for the i'th hash.
+        let k_and_m = ih1.wrapping_add((i as u64).wrapping_mul(ih2));
+
+        // The hasher the reducer may use to derive more hashes.
+        let mut h3: G = Default::default();
+
+        let (ix, rejections) = self.reducer.reduce_counted(&mut h3, k_and_m);
+        self.count_rejections(rejections);
+        ix
+    }
+}
+
+impl<H, T> StandardBloom<H, T, RejectionSampling> {
+    /// The mask applied to each hash before it's checked against the
+    /// number of bits. It always covers every bit index.
+    pub fn index_mask(&self) -> u64 {
+        self.reducer.mask()
+    }
+}
+
+/// Mark every borrowed item from an iterator, so a filter can be
+/// extended from a slice without cloning its items.
+impl<'a, H, T, R> Extend<&'a T> for StandardBloom<H, T, R>
+where
+    H: Hasher + Default,
+    T: Hash + 'a,
+    R: IndexReducer,
+{
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        for item in iter {
+            self.mark(item);
+        }
+    }
+}
+
+/// Build a filter sized for exactly the collected items with a false
+/// positive probability of about `DEFAULT_FALSE_POSITIVE_RATE`. The
+/// size isn't known until every item has been seen, so the items are
+/// first gathered into a `Vec` and then marked in a second pass.
+impl<H, T, R> FromIterator<T> for StandardBloom<H, T, R>
+where
+    H: Hasher + Default,
+    T: Hash,
+    R: IndexReducer,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let items: Vec<T> = iter.into_iter().collect();
+        StandardBloom::built_from(&items, DEFAULT_FALSE_POSITIVE_RATE)
+    }
+}
+
+/// A faster path for filters of `u64` keys, such as IDs, that skips the
+/// hasher `H` and mixes the key with SplitMix64 instead. Hashes the
+/// reducer rejects are rehashed with SplitMix64 too, and the bit
+/// indices are visited one at a time rather than collected.
+///
+/// The bits a key sets this way differ from the bits `mark` sets for
+/// the same key, so a filter must use either these methods or `mark`
+/// and `check`, never both.
+impl<H, R> StandardBloom<H, u64, R>
+where
+    H: Hasher + Default,
+    R: IndexReducer,
+{
+    /// Mark `key` using the `u64` fast path.
+    pub fn mark_u64(&mut self, key: u64) {
+        let (h1, h2) = self.base_hashes_u64(key);
+        for i in 0..self.k {
+            let ix = self.bit_index::<SplitMixHasher>(h1, h2, i);
+            self.set_bit(ix);
+        }
+    }
+
+    /// Check `key` using the `u64` fast path.
+    pub fn check_u64(&self, key: u64) -> bool {
+        let (h1, h2) = self.base_hashes_u64(key);
+        (0..self.k).all(|i| self.bits.get(self.bit_index::<SplitMixHasher>(h1, h2, i)))
+    }
+
+    /// Mix `key` with each seed to get the two base hashes.
+    fn base_hashes_u64(&self, key: u64) -> (u64, u64) {
+        (splitmix64(key ^ self.seed1), splitmix64(key ^ self.seed2))
+    }
+}
+
+/// Since `check` only reads the filter, a StandardBloom can be queried
+/// from many threads at once without any synchronization.
+#[cfg(feature = "rayon")]
+impl<H, T, R> StandardBloom<H, T, R>
+where
+    H: Hasher + Default + Sync,
+    T: Hash + Sync,
+    R: IndexReducer + Sync,
+{
+    /// Check every item in `items` in parallel, returning the result
+    /// of `check` for each one in the same order.
+    pub fn par_check(&self, items: &[T]) -> Vec<bool> {
+        items.par_iter().map(|item| self.check(item)).collect()
+    }
+}
+
+/// Split a 128-bit digest into the two base hashes, low half first.
+fn split_digest(digest: u128) -> (u64, u64) {
+    (digest as u64, (digest >> 64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use bloom::false_positive_probability;
+    use reducer::{Lemire, Modulo};
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashSet;
+    use super::*;
+
+    /// A hasher an attacker might wish for: every hash is the same, and
+    /// is never a usable bit index unless the bits are a power of two.
+    #[derive(Default)]
+    struct StuckHasher;
+
+    impl Hasher for StuckHasher {
+        fn finish(&self) -> u64 {
+            u64::MAX
+        }
+
+        fn write(&mut self, _bytes: &[u8]) {}
+    }
+
+    #[test]
+    fn the_basics_work() {
         let mut bb: DefaultStandardBloom<usize> =
             StandardBloom::new(1024 * 1024, 16, optimal_hashers(16));
         assert!(!bb.check(&100));
         bb.mark(&100);
         assert!(bb.check(&100));
     }
+
+    #[test]
+    fn auto_k_is_optimal() {
+        let bb: DefaultStandardBloom<usize> = StandardBloom::new_auto_k(1024, 16);
+        assert!(bb.hash_count() == optimal_hashers(16).min(16));
+
+        let bb: DefaultStandardBloom<usize> = StandardBloom::new_auto_k(1024, 1);
+        assert!(bb.hash_count() == 1);
+    }
+
+    #[test]
+    fn matching_bits_counts_set_bits() {
+        let mut bb: DefaultStandardBloom<usize> =
+            StandardBloom::new(1024, 16, optimal_hashers(16));
+        bb.mark(&100);
+
+        assert!(bb.matching_bits(&100) == bb.hash_count());
+        assert!(bb.matching_bits(&101) < bb.hash_count());
+    }
+
+    // With `stats`, the counters are mutable through `&self`, but they
+    // aren't part of the hash.
+    #[cfg_attr(feature = "stats", allow(clippy::mutable_key_type))]
+    #[test]
+    fn equal_filters_hash_equal() {
+        let mut a: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        let mut b: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        let mut c: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        a.mark(&100);
+        b.mark(&100);
+        c.mark(&200);
+
+        assert!(a == b);
+        assert!(a != c);
+
+        let mut set = HashSet::new();
+        set.insert(a);
+        set.insert(b);
+        set.insert(c);
+        assert!(set.len() == 2);
+    }
+
+    #[test]
+    fn suboptimal_k_is_detected() {
+        let bb: DefaultStandardBloom<usize> = StandardBloom::new(1024, 16, 2);
+        assert!(!bb.parameters_are_optimal());
+        assert!(bb.suggested_k() == optimal_hashers(16));
+
+        let bb: DefaultStandardBloom<usize> = StandardBloom::new(1024, 16, optimal_hashers(16));
+        assert!(bb.parameters_are_optimal());
+    }
+
+    #[test]
+    fn intersection_ones_counts_shared_bits() {
+        let mut a: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        let mut b: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        for i in 0..600 {
+            a.mark(&i);
+        }
+        for i in 400..1000 {
+            b.mark(&i);
+        }
+
+        let expected = (0..a.bits.width())
+            .filter(|ix| a.bits.get(*ix) && b.bits.get(*ix))
+            .count();
+        assert!(a.intersection_ones(&b) == Ok(expected));
+
+        let c: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 3);
+        assert!(a.intersection_ones(&c) == Err(MergeError::MismatchedSeeds));
+
+        let d: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 4, 1, 2);
+        assert!(a.intersection_ones(&d) == Err(MergeError::MismatchedParameters));
+    }
+
+    #[test]
+    fn marking_with_hashes_matches_items() {
+        let mut a: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        let mut b: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+
+        for i in 0..100 {
+            a.mark(&i);
+
+            let (h1, h2) = b.base_hashes(&i);
+            b.mark_with_hash(h1, h2);
+        }
+
+        assert!(a == b);
+
+        let (h1, h2) = a.base_hashes(&50);
+        assert!(a.check_with_hash(h1, h2));
+        assert!(b.check(&50));
+    }
+
+    #[test]
+    fn decoupled_k_can_exceed_bits_per_member() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new_decoupled(100, 20, 1, 2);
+        assert!(bb.hash_count() == 20);
+        assert!(bb.bits.width() == 100);
+
+        bb.mark(&7);
+        assert!(bb.check(&7));
+        assert!(bb.bit_indices(&7).len() == 20);
+        assert!(bb.bits.count_ones() <= 20);
+    }
+
+    #[test]
+    fn decoupled_k_can_exceed_total_bits() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new_decoupled(16, 40, 1, 2);
+        assert!(bb.hash_count() == 40);
+
+        bb.mark(&7);
+        assert!(bb.check(&7));
+        assert!(bb.bit_indices(&7).len() == 40);
+        assert!(bb.validate() == Ok(()));
+
+        let rt: DefaultStandardBloom<usize> = StandardBloom::from_bytes(&bb.to_bytes()).unwrap();
+        assert!(rt == bb);
+    }
+
+    #[test]
+    fn capacity_at_fp_keeps_rate_under_target() {
+        let bb: DefaultStandardBloom<usize> = StandardBloom::new(1000, 10, 7);
+        let predicted = |n: usize| {
+            let (m, k) = (bb.bits.width() as f64, bb.k as f64);
+            (1.0 - (-k * n as f64 / m).exp()).powf(k)
+        };
+
+        for &target in &[0.001, 0.01, 0.1] {
+            let capacity = bb.capacity_at_fp(target);
+            assert!(predicted(capacity) <= target);
+            assert!(predicted(capacity + 1) > target);
+        }
+
+        // The filter was sized for 1000 members at about 0.8%.
+        assert!(bb.capacity_at_fp(0.01) > 1000);
+        assert!(bb.capacity_at_fp(0.001) < 1000);
+    }
+
+    #[test]
+    fn distinct_indices_can_be_fewer_than_k() {
+        let bb: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1, 8, 8, 1, 2);
+        let distinct: Vec<usize> = (0..100).map(|i| bb.distinct_indices(&i)).collect();
+
+        assert!(distinct.iter().all(|d| *d >= 1 && *d <= 8));
+        assert!(distinct.iter().any(|d| *d < 8));
+    }
+
+    #[test]
+    fn reset_to_seeds_matches_a_new_filter() {
+        let mut reused: DefaultStandardBloom<usize> = StandardBloom::new(1024, 16, 8);
+        for i in 0..100 {
+            reused.mark(&i);
+        }
+        reused.reset_to_seeds(3, 4);
+
+        let mut fresh: DefaultStandardBloom<usize> =
+            StandardBloom::new_with_seeds(1024, 16, 8, 3, 4);
+        assert!(reused == fresh);
+        assert!(!reused.check(&50));
+
+        for i in 500..600 {
+            reused.mark(&i);
+            fresh.mark(&i);
+        }
+        assert!(reused == fresh);
+        assert!(reused.base_hashes(&7) == fresh.base_hashes(&7));
+    }
+
+    #[test]
+    fn ascii_art_draws_every_bit() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(4, 16, 4, 1, 2);
+        bb.mark(&1);
+
+        let art = bb.ascii_art(16);
+        let rows: Vec<&str> = art.lines().collect();
+        assert!(art.chars().count() == 64 + 4);
+        assert!(rows.len() == 4 && rows.iter().all(|r| r.chars().count() == 16));
+
+        let drawn: Vec<bool> = rows.concat().chars().map(|ch| ch == '█').collect();
+        assert!(drawn == bb.bits.bits().collect::<Vec<_>>());
+        assert!(bb.bit_indices(&1).iter().all(|ix| drawn[*ix]));
+
+        assert!(bb.ascii_art(60).lines().map(|r| r.chars().count()).eq(vec![60, 4]));
+    }
+
+    #[test]
+    fn u64_fast_path_finds_members() {
+        let mut bb: StandardBloom<DefaultHasher, u64> = StandardBloom::new(10_000, 10, 7);
+        for key in 0..10_000 {
+            bb.mark_u64(key * 7919);
+        }
+
+        assert!((0..10_000).all(|key| bb.check_u64(key * 7919)));
+
+        // About 0.8% of non-members are expected to be false positives.
+        let false_positives = (0..10_000).filter(|key| bb.check_u64(key * 7919 + 1)).count();
+        assert!(false_positives < 200);
+    }
+
+    #[test]
+    fn u64_fast_path_never_uses_the_hasher() {
+        // StuckHasher would rehash forever if a rejected hash were
+        // handed to it, since 1000 * 10 bits isn't a power of two.
+        let mut bb: StandardBloom<StuckHasher, u64> = StandardBloom::new(1000, 10, 7);
+        for key in 0..1000 {
+            bb.mark_u64(key);
+        }
+
+        assert!((0..1000).all(|key| bb.check_u64(key)));
+    }
+
+    #[test]
+    fn corrupted_filter_fails_validation() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(1000, 10, 7);
+        for i in 0..500 {
+            bb.mark(&i);
+        }
+        assert!(bb.validate() == Ok(()));
+
+        let rt: DefaultStandardBloom<usize> = StandardBloom::from_bytes(&bb.to_bytes()).unwrap();
+        assert!(rt.validate() == Ok(()));
+
+        let mut corrupt = bb.clone();
+        corrupt.k = 0;
+        assert!(corrupt.validate() == Err("k is zero"));
+
+        let mut corrupt = bb.clone();
+        corrupt.reducer = RejectionSampling::new(1 << 20);
+        assert!(corrupt.validate().is_err());
+
+        let mut corrupt = bb.clone();
+        corrupt.ones += 1;
+        assert!(corrupt.validate() == Err("the count of set bits is out of date"));
+
+        // A MsbFirst filter keeps its padding in the low bits of the
+        // last word, so the high bits of that word can be set.
+        let bits = BitArray::new_with_order(1000, BitOrder::MsbFirst);
+        let mut msb: DefaultStandardBloom<usize> = StandardBloom::from_bit_array(bits, 7, 1, 2);
+        msb.set_bit(960);
+        assert!(msb.validate() == Ok(()));
+    }
+
+    #[test]
+    fn digests_split_into_base_hashes() {
+        let mut a: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        let mut b: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+
+        for i in 0..100 {
+            a.mark(&i);
+
+            let (h1, h2) = b.base_hashes(&i);
+            b.mark_digest((h2 as u128) << 64 | h1 as u128);
+        }
+
+        assert!(a == b);
+
+        let (h1, h2) = a.base_hashes(&50);
+        assert!(a.check_digest((h2 as u128) << 64 | h1 as u128));
+    }
+
+    #[test]
+    fn from_external_bit_array() {
+        let mut bits = BitArray::new(16384);
+        bits.set(5);
+
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::from_bit_array(bits, 7, 1, 2);
+        assert!(bb.bits.width() == 16384);
+        assert!(bb.bits.get(5));
+        assert!(bb.hash_count() == 7);
+        assert!(bb.set_size() * bb.bits_per_member() == 16384);
+        assert!(bb.bits_per_member() == 16);
+
+        for i in 0..1000 {
+            bb.mark(&i);
+        }
+        assert!((0..1000).all(|i| bb.check(&i)));
+
+        let rt: DefaultStandardBloom<usize> = StandardBloom::from_bytes(&bb.to_bytes()).unwrap();
+        assert!(rt == bb);
+
+        // A prime width has no divisors to choose from.
+        let odd: DefaultStandardBloom<usize> =
+            StandardBloom::from_bit_array(BitArray::new(1009), 7, 1, 2);
+        assert!(odd.set_size() == 1 && odd.bits_per_member() == 1009);
+    }
+
+    #[test]
+    fn clear_keeps_width_and_reinit_changes_it() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(1000, 10, 7);
+        bb.mark(&100);
+
+        bb.clear();
+        assert!(bb.bits.width() == 10000);
+        assert!(bb.bits.count_ones() == 0);
+        assert!(!bb.check(&100));
+
+        bb.mark(&100);
+        bb.reinit(500, 16, 11);
+        assert!(bb.bits.width() == 8000);
+        assert!(bb.bits.count_ones() == 0);
+        assert!(bb.set_size() == 500 && bb.bits_per_member() == 16 && bb.hash_count() == 11);
+
+        for i in 0..500 {
+            bb.mark(&i);
+        }
+        assert!((0..500).all(|i| bb.check(&i)));
+    }
+
+    #[test]
+    fn check_bounded_limits_hashing() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(1000, 10, 7);
+        for i in 0..500 {
+            bb.mark(&i);
+        }
+
+        for i in 0..1000 {
+            assert!(bb.check_bounded(&i, 1000) == Ok(bb.check(&i)));
+        }
+
+        let stuck: StandardBloom<StuckHasher, usize> = StandardBloom::new(1000, 10, 7);
+        assert!(stuck.check_bounded(&1, 100) == Err(HashBudgetExceeded));
+    }
+
+    #[test]
+    fn display_is_compact() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(1024, 16, 11);
+        assert!(bb.to_string() == "StandardBloom(n=1024, c=16, k=11, fill=0.0%)");
+
+        bb.mark(&100);
+        let s = bb.to_string();
+        assert!(s.contains("n=1024") && s.contains("c=16") && s.contains("k=11"));
+        assert!(s.contains('%'));
+    }
+
+    #[test]
+    fn compatibility_is_reported() {
+        let a: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        let mut b: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        b.mark(&100);
+        assert!(a.is_compatible_with(&b) && b.is_compatible_with(&a));
+
+        let others: Vec<DefaultStandardBloom<usize>> = vec![
+            StandardBloom::new_with_seeds(1024, 16, 8, 1, 3),
+            StandardBloom::new_with_seeds(1024, 16, 7, 1, 2),
+            StandardBloom::new_with_seeds(2048, 8, 8, 1, 2),
+            StandardBloom::new_pow2_with_seeds(1000, 16, 8, 1, 2),
+        ];
+        for other in &others {
+            assert!(!a.is_compatible_with(other));
+        }
+    }
+
+    #[test]
+    fn merged_leaves_operands_alone() {
+        let mut a: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        let mut b: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        for i in 0..100 {
+            a.mark(&i);
+            b.mark(&(i + 100));
+        }
+        let (a_words, b_words) = (a.bits.as_words().to_vec(), b.bits.as_words().to_vec());
+
+        let m = StandardBloom::merged(&a, &b).unwrap();
+        assert!((0..200).all(|i| m.check(&i)));
+        assert!(a.bits.as_words() == &a_words[..]);
+        assert!(b.bits.as_words() == &b_words[..]);
+
+        let c: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 3, 4);
+        assert!(StandardBloom::merged(&a, &c).err() == Some(MergeError::MismatchedSeeds));
+    }
+
+    #[test]
+    fn index_mask_covers_the_bits() {
+        for n in &[1, 7, 100, 1000, 1024] {
+            let bb: DefaultStandardBloom<usize> = StandardBloom::new(*n, 10, 7);
+            let bits = bb.bits.width() as u64;
+            assert!(bb.index_mask() >= bits - 1);
+            assert!(bb.index_mask() < bits * 2);
+            assert!((bb.index_mask() + 1).is_power_of_two());
+        }
+    }
+
+    #[test]
+    fn mark_reporting_new_counts_new_bits() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(1000, 10, 7);
+
+        let new = bb.mark_reporting_new(&1);
+        assert!(new > 0 && new <= 7);
+        assert!(bb.bits.count_ones() == new);
+        assert!(bb.mark_reporting_new(&1) == 0);
+    }
+
+    #[test]
+    fn indices_for_many_matches_bit_indices() {
+        let bb: DefaultStandardBloom<usize> = StandardBloom::new(1000, 10, 7);
+        let items: Vec<usize> = (0..100).collect();
+
+        let many = bb.indices_for_many(&items);
+        assert!(many.len() == items.len());
+        for (item, indices) in items.iter().zip(&many) {
+            assert!(*indices == bb.bit_indices(item));
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_filters_persist() {
+        let path = std::env::temp_dir().join(format!("baffles-mmap-{}.bin", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        {
+            let mut bb: DefaultStandardBloom<usize> =
+                StandardBloom::open_mmap(&path, 1000, 10, 7, 1, 2).unwrap();
+            for i in 0..500 {
+                bb.mark(&i);
+            }
+            bb.flush().unwrap();
+        }
+
+        assert!(std::fs::metadata(&path).unwrap().len() == 157 * 8);
+
+        let reopened: DefaultStandardBloom<usize> =
+            StandardBloom::open_mmap(&path, 1000, 10, 7, 1, 2).unwrap();
+        assert!((0..500).all(|i| reopened.check(&i)));
+
+        let mut heap: DefaultStandardBloom<usize> =
+            StandardBloom::new_with_seeds(1000, 10, 7, 1, 2);
+        heap.extend(&(0..500).collect::<Vec<_>>());
+        assert!(reopened == heap);
+
+        let wrong_size = StandardBloom::<DefaultHasher, usize>::open_mmap(&path, 2000, 10, 7, 1, 2);
+        assert!(wrong_size.is_err());
+
+        drop(reopened);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn mark_lines_marks_trimmed_keys() {
+        let input = "apple\n  banana  \n\ncherry\r\ndate";
+        let mut bb: DefaultStandardBloom<String> = StandardBloom::new(1024, 16, 8);
+
+        assert!(bb.mark_lines(io::Cursor::new(input)).unwrap() == 4);
+        for key in &["apple", "banana", "cherry", "date"] {
+            assert!(bb.check_borrowed(*key));
+            assert!(bb.check(&key.to_string()));
+        }
+        assert!(!bb.check_borrowed("  banana  "));
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn query_stats_count_checks() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(1024, 16, 8);
+        bb.mark(&1);
+        bb.mark(&2);
+
+        for i in &[1, 2, 1, 1000, 2000, 2] {
+            bb.check(i);
+        }
+
+        let stats = bb.query_stats();
+        assert!(stats.hits == 4);
+        assert!(stats.misses == 2);
+        assert!(stats.queries == 6);
+        assert!(bb.clone().query_stats() == stats);
+    }
+
+    #[cfg(feature = "timing")]
+    #[test]
+    fn build_timed_reports_timing() {
+        let (bb, timing): (DefaultStandardBloom<usize>, _) =
+            StandardBloom::build_timed(1024, 16, 8, 0..1000);
+
+        assert!((0..1000).all(|i| bb.check(&i)));
+        assert!(timing.items == 1000);
+        assert!(timing.total > Duration::default());
+        assert!(timing.hashing > Duration::default());
+        assert!(timing.hashing <= timing.total);
+    }
+
+    #[test]
+    fn mark_from_sources_marks_every_shard() {
+        let shards = vec![vec![1, 2, 3], vec![], vec![100, 200], vec![5000]];
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(1024, 16, 8);
+
+        bb.mark_from_sources(shards.clone());
+        assert!(shards.iter().flatten().all(|i| bb.check(i)));
+    }
+
+    #[test]
+    fn measured_fp_rate_is_near_theory() {
+        let mut bb: StandardBloom<DefaultHasher, u64> = StandardBloom::new(1000, 10, 7);
+        for i in 0..1000 {
+            bb.mark(&i);
+        }
+
+        let theory = false_positive_probability(1000, 10, 7);
+        let measured = bb.measure_fp_rate(&mut rand::thread_rng(), 200_000);
+        assert!(measured > theory * 0.5 && measured < theory * 2.0);
+    }
+
+    #[test]
+    fn collect_builds_a_sized_filter() {
+        let bb: StandardBloom<DefaultHasher, u64> = (0..1000).collect();
+        assert!(bb.set_size() == 1000);
+        assert!(bb.bits_per_member() == optimal_bits_per_member(DEFAULT_FALSE_POSITIVE_RATE));
+        assert!((0..1000).all(|i| bb.check(&i)));
+    }
+
+    #[test]
+    fn effective_k_tracks_fill() {
+        let k = optimal_hashers(16);
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(1000, 16, k);
+        assert!(bb.effective_k_for_current_fill() == f64::INFINITY);
+
+        for i in 0..250 {
+            bb.mark(&i);
+        }
+        assert!(bb.effective_k_for_current_fill() > k as f64 * 2.0);
+
+        for i in 250..1000 {
+            bb.mark(&i);
+        }
+        // Once full, the best k is the unrounded optimum for c.
+        let best = 16.0 * std::f64::consts::LN_2;
+        assert!((bb.effective_k_for_current_fill() - best).abs() < 0.5);
+
+        for i in 1000..4000 {
+            bb.mark(&i);
+        }
+        assert!(bb.effective_k_for_current_fill() < k as f64 / 2.0);
+    }
+
+    #[test]
+    fn union_or_rebuild_merges_compatible_filters() {
+        let mut a: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        let mut b: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        for i in 0..100 {
+            b.mark(&i);
+        }
+
+        // The fallback is never consumed on the fast path.
+        assert!(a.union_or_rebuild(&b, (0..100).map(|_| -> usize { unreachable!() })));
+        assert!(a == b);
+    }
+
+    #[test]
+    fn union_or_rebuild_marks_the_fallback_items() {
+        let mut a: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        let mut b: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 3, 4);
+        a.mark(&5000);
+        for i in 0..100 {
+            b.mark(&i);
+        }
+
+        assert!(!a.union_or_rebuild(&b, 0..100));
+        assert!(a.check(&5000));
+        assert!((0..100).all(|i| a.check(&i)));
+    }
+
+    #[test]
+    fn union_all_combines_members() {
+        let mut filters: Vec<DefaultStandardBloom<usize>> = (0..3)
+            .map(|_| StandardBloom::new_with_seeds(1024, 16, 8, 1, 2))
+            .collect();
+        for (i, filter) in filters.iter_mut().enumerate() {
+            for item in i * 100..(i + 1) * 100 {
+                filter.mark(&item);
+            }
+        }
+
+        let combined = StandardBloom::union_all(&filters).unwrap();
+        assert!((0..300).all(|i| combined.check(&i)));
+        assert!(combined.bits.count_ones() >= filters[0].bits.count_ones());
+
+        filters.push(StandardBloom::new_with_seeds(1024, 16, 8, 3, 4));
+        assert!(StandardBloom::union_all(&filters).err() == Some(MergeError::MismatchedSeeds));
+        assert!(DefaultStandardBloom::<usize>::union_all(&[]).err() == Some(MergeError::NoFilters));
+    }
+
+    #[test]
+    fn pow2_filters_never_reject() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new_pow2(1000, 10, 7);
+        assert!(bb.bits.width() == 16384);
+
+        for i in 0..1000 {
+            bb.mark(&i);
+        }
+
+        assert!((0..1000).all(|i| bb.check(&i)));
+        #[cfg(feature = "stats")]
+        assert!(bb.rejection_count() == 0);
+
+        let rt: DefaultStandardBloom<usize> = StandardBloom::from_bytes(&bb.to_bytes()).unwrap();
+        assert!(rt == bb);
+
+        let plain: DefaultStandardBloom<usize> =
+            StandardBloom::new_with_seeds(1000, 10, 7, bb.seed1, bb.seed2);
+        assert!(bb.intersection_ones(&plain) == Err(MergeError::MismatchedParameters));
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(1000, 10, 7);
+        for i in 0..500 {
+            bb.mark(&i);
+        }
+
+        let bytes = bb.to_bytes();
+        assert!(bytes.len() == (HEADER_WORDS + 157) * 8);
+
+        let rt: DefaultStandardBloom<usize> = StandardBloom::from_bytes(&bytes).unwrap();
+        assert!(rt == bb);
+        for i in 0..500 {
+            assert!(rt.check(&i));
+        }
+
+        assert!(DefaultStandardBloom::<usize>::from_bytes(&bytes[..bytes.len() - 8]).is_err());
+        assert!(DefaultStandardBloom::<usize>::from_bytes(&bytes[..3]).is_err());
+    }
+
+    #[test]
+    fn msb_first_bytes_round_trip() {
+        let bits = BitArray::new_with_order(1000, BitOrder::MsbFirst);
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::from_bit_array(bits, 7, 1, 2);
+        for i in 0..100 {
+            bb.mark(&i);
+        }
+
+        let rt: DefaultStandardBloom<usize> = StandardBloom::from_bytes(&bb.to_bytes()).unwrap();
+        assert!(rt.bits.order() == BitOrder::MsbFirst);
+        assert!(rt == bb);
+        assert!((0..100).all(|i| rt.check(&i)));
+    }
+
+    #[test]
+    fn backing_bytes_borrow_every_word() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(1000, 10, 7);
+        for i in 0..500 {
+            bb.mark(&i);
+        }
+
+        let words = bb.bits.as_words();
+        let bytes = bb.backing_bytes();
+        assert!(bytes.len() == words.len() * 8);
+
+        let native: Vec<u8> = words.iter().flat_map(|w| w.to_ne_bytes()).collect();
+        assert!(bytes == &native[..]);
+        assert!(bytes[..] == bb.to_bytes()[HEADER_WORDS * 8..] || cfg!(target_endian = "big"));
+    }
+
+    #[test]
+    fn framed_filters_stream_back_to_back() {
+        let mut a: DefaultStandardBloom<usize> = StandardBloom::new(1000, 10, 7);
+        let mut b: DefaultStandardBloom<usize> = StandardBloom::new(64, 16, 8);
+        for i in 0..500 {
+            a.mark(&i);
+        }
+        b.mark(&9999);
+
+        let mut stream = io::Cursor::new(Vec::new());
+        a.write_framed(&mut stream).unwrap();
+        b.write_framed(&mut stream).unwrap();
+
+        let written = stream.into_inner();
+        assert!(written[..4] == (a.to_bytes().len() as u32).to_be_bytes());
+
+        let mut stream = io::Cursor::new(written);
+        let rt_a = DefaultStandardBloom::<usize>::read_framed(&mut stream).unwrap();
+        let rt_b = DefaultStandardBloom::<usize>::read_framed(&mut stream).unwrap();
+        assert!(rt_a == a);
+        assert!(rt_b == b);
+
+        let err = DefaultStandardBloom::<usize>::read_framed(&mut stream).unwrap_err();
+        assert!(err.kind() == io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn framed_filter_with_a_lying_prefix_is_truncated() {
+        let mut framed = u32::MAX.to_be_bytes().to_vec();
+        framed.extend_from_slice(&[0; 64]);
+
+        let mut stream = io::Cursor::new(framed);
+        let err = DefaultStandardBloom::<usize>::read_framed(&mut stream).unwrap_err();
+        assert!(err.kind() == io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn other_reducers_work() {
+        let mut modulo: StandardBloom<DefaultHasher, usize, Modulo> =
+            StandardBloom::new(1000, 10, 7);
+        let mut lemire: StandardBloom<DefaultHasher, usize, Lemire> =
+            StandardBloom::new(1000, 10, 7);
+
+        for i in 0..1000 {
+            modulo.mark(&i);
+            lemire.mark(&i);
+        }
+
+        for i in 0..1000 {
+            assert!(modulo.check(&i));
+            assert!(lemire.check(&i));
+        }
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn rejections_are_counted() {
+        // 1000 * 10 bits masks to 16384, so roughly 40% of hashes are
+        // rejected.
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(1000, 10, 7);
+        assert!(bb.rejection_count() == 0);
+
+        for i in 0..1000 {
+            bb.mark(&i);
+        }
+
+        assert!(bb.rejection_count() > 0);
+
+        // Reducers that never reject report nothing.
+        let mut modulo: StandardBloom<DefaultHasher, usize, Modulo> =
+            StandardBloom::new(1000, 10, 7);
+        for i in 0..1000 {
+            modulo.mark(&i);
+        }
+
+        assert!(modulo.rejection_count() == 0);
+    }
+
+    #[cfg(feature = "ahash")]
+    #[test]
+    fn ahash_filter_works() {
+        let mut bb: AHashStandardBloom<usize> = StandardBloom::new(1024, 16, optimal_hashers(16));
+        assert!(!bb.check(&100));
+        bb.mark(&100);
+        assert!(bb.check(&100));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_check_matches_check() {
+        fn assert_sync<S: Sync>(_: &S) {}
+
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(1024, 16, optimal_hashers(16));
+        assert_sync(&bb);
+
+        for i in (0..1024).filter(|i| i % 3 == 0) {
+            bb.mark(&i);
+        }
+
+        let items: Vec<usize> = (0..10000).collect();
+        let expected: Vec<bool> = items.iter().map(|i| bb.check(i)).collect();
+        assert!(bb.par_check(&items) == expected);
+    }
+
+    #[test]
+    fn resize_keeps_members() {
+        let mut bb: DefaultStandardBloom<usize> = StandardBloom::new(100, 8, 5);
+        for i in 0..100 {
+            bb.mark(&i);
+        }
+
+        let grown = bb.resize_from(10000, 16, optimal_hashers(16), 0..100);
+        assert!(grown.set_size() == 10000);
+        assert!(grown.bits_per_member() == 16);
+        for i in 0..100 {
+            assert!(grown.check(&i));
+        }
+    }
+
+    #[test]
+    fn extend_from_borrowed_items() {
+        let items: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+
+        let mut bb: DefaultStandardBloom<String> =
+            StandardBloom::new(1024, 16, optimal_hashers(16));
+        bb.extend(items.iter());
+        bb.extend(&items[..10]);
+
+        for item in &items {
+            assert!(bb.check(item));
+        }
+    }
+
+    #[test]
+    fn hash_into_matches_bit_indices() {
+        let bb: DefaultStandardBloom<usize> = StandardBloom::new(1024, 16, optimal_hashers(16));
+
+        let mut buf = vec![1, 2, 3];
+        for i in 0..100 {
+            bb.hash_into(&i, &mut buf);
+            assert!(buf == bb.bit_indices(&i));
+        }
+    }
+
+    #[test]
+    fn built_from_contains_items() {
+        let items: Vec<usize> = (0..10000).collect();
+        let bb: DefaultStandardBloom<usize> = StandardBloom::built_from(&items, 0.01);
+
+        assert!(bb.set_size() == items.len());
+        for i in &items {
+            assert!(bb.check(i));
+        }
+
+        let probes = 10000..110000;
+        let fp = probes.clone().filter(|i| bb.check(i)).count() as f64 / probes.len() as f64;
+        assert!(fp < 0.015);
+    }
 }
@@ -0,0 +1,119 @@
+//! A bloom filter that can be shared between threads.
+//!
+//! `SyncBloom` wraps any BloomFilter in an `RwLock`, so any number of
+//! threads may `check` at once while `mark` waits for exclusive access.
+
+use bloom::BloomFilter;
+use std::hash::Hash;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// A BloomFilter behind an `RwLock`.
+///
+/// ```
+/// use baffles::standard::*;
+/// use baffles::sync::SyncBloom;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let filter: DefaultStandardBloom<usize> = StandardBloom::new(1024, 16, 8);
+/// let shared = Arc::new(SyncBloom::new(filter));
+///
+/// let writer = shared.clone();
+/// thread::spawn(move || writer.mark(&100)).join().unwrap();
+///
+/// assert!(shared.check(&100));
+/// ```
+#[derive(Debug)]
+pub struct SyncBloom<B> {
+    filter: RwLock<B>,
+}
+
+impl<B> SyncBloom<B> {
+    /// Wrap `filter` so it can be shared between threads.
+    pub fn new(filter: B) -> Self {
+        SyncBloom {
+            filter: RwLock::new(filter),
+        }
+    }
+
+    /// Set the bits for `item`, waiting for any readers to finish.
+    pub fn mark<T: Hash>(&self, item: &T)
+    where
+        B: BloomFilter<T>,
+    {
+        self.write().mark(item)
+    }
+
+    /// True if the bits for `item` are all set.
+    pub fn check<T: Hash>(&self, item: &T) -> bool
+    where
+        B: BloomFilter<T>,
+    {
+        self.read().check(item)
+    }
+
+    /// Unwrap the filter.
+    pub fn into_inner(self) -> B {
+        match self.filter.into_inner() {
+            Ok(f) => f,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    // A thread that panics while marking leaves the lock poisoned. The
+    // filter is still usable, since bits are only ever set: at worst
+    // the item being marked is only partly present.
+
+    fn read(&self) -> RwLockReadGuard<'_, B> {
+        self.filter.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, B> {
+        self.filter.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use standard::{DefaultStandardBloom, StandardBloom};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn concurrent_readers_and_a_writer() {
+        let filter: DefaultStandardBloom<usize> = StandardBloom::new(10000, 16, 8);
+        let shared = Arc::new(SyncBloom::new(filter));
+        for i in 0..1000 {
+            shared.mark(&i);
+        }
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        assert!((0..1000).all(|i| shared.check(&i)));
+                    }
+                })
+            })
+            .collect();
+
+        let writer = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for i in 1000..2000 {
+                    shared.mark(&i);
+                }
+            })
+        };
+
+        for r in readers {
+            r.join().unwrap();
+        }
+        writer.join().unwrap();
+
+        let filter = Arc::try_unwrap(shared).unwrap().into_inner();
+        assert!((0..2000).all(|i| filter.check(&i)));
+    }
+}
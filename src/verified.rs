@@ -0,0 +1,158 @@
+//! A bloom filter that checks itself against an exact set.
+//!
+//! `VerifiedBloom` keeps every marked item in a `HashSet` alongside the
+//! filter, and panics if the filter ever reports that a marked item is
+//! missing. A bloom filter must never give a false negative, so this is
+//! a way to catch bugs in a filter while testing. It doubles the memory
+//! used and then some, so it's only available with the `verified`
+//! feature.
+
+use bloom::BloomFilter;
+use std::any::Any;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// A BloomFilter that panics on a false negative.
+///
+/// ```
+/// use baffles::standard::*;
+/// use baffles::verified::VerifiedBloom;
+///
+/// let filter: DefaultStandardBloom<usize> = StandardBloom::new(1024, 16, 8);
+/// let mut verified = VerifiedBloom::new(filter);
+///
+/// verified.mark(&100);
+/// assert!(verified.check(&100));
+/// ```
+#[derive(Debug, Clone)]
+pub struct VerifiedBloom<B, T: Eq + Hash> {
+    /// The filter being verified.
+    filter: B,
+
+    /// Every item marked in the filter.
+    members: HashSet<T>,
+}
+
+impl<B, T: Eq + Hash> VerifiedBloom<B, T> {
+    /// Verify `filter`, which should be empty: items marked before it's
+    /// wrapped aren't known to the exact set.
+    pub fn new(filter: B) -> Self {
+        VerifiedBloom {
+            filter,
+            members: HashSet::new(),
+        }
+    }
+
+    /// The filter being verified.
+    pub fn get_ref(&self) -> &B {
+        &self.filter
+    }
+
+    /// Unwrap the filter.
+    pub fn into_inner(self) -> B {
+        self.filter
+    }
+}
+
+impl<B, T> BloomFilter<T> for VerifiedBloom<B, T>
+where
+    B: BloomFilter<T> + Clone,
+    T: Eq + Hash + Clone,
+{
+    fn name(&self) -> &str {
+        self.filter.name()
+    }
+
+    fn mark(&mut self, item: &T) {
+        self.filter.mark(item);
+        self.members.insert(item.clone());
+    }
+
+    /// Panics if the filter doesn't find an item that was marked.
+    fn check(&self, item: &T) -> bool {
+        let found = self.filter.check(item);
+        assert!(
+            found || !self.members.contains(item),
+            "false negative from the {} filter",
+            self.filter.name()
+        );
+        found
+    }
+
+    fn clear(&mut self) {
+        self.filter.clear();
+        self.members.clear();
+    }
+
+    fn set_size(&self) -> usize {
+        self.filter.set_size()
+    }
+
+    fn bits_per_member(&self) -> usize {
+        self.filter.bits_per_member()
+    }
+
+    fn hash_count(&self) -> usize {
+        self.filter.hash_count()
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.filter.memory_bytes()
+    }
+
+    fn as_any(&self) -> &dyn Any
+    where
+        Self: 'static,
+    {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any
+    where
+        Self: 'static,
+    {
+        self
+    }
+
+    fn box_clone(&self) -> Box<dyn BloomFilter<T>>
+    where
+        Self: 'static,
+    {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use standard::{DefaultStandardBloom, StandardBloom};
+
+    #[test]
+    fn wraps_a_standard_filter_transparently() {
+        let filter: DefaultStandardBloom<usize> = StandardBloom::new_with_seeds(1024, 16, 8, 1, 2);
+        let mut plain = filter.clone();
+        let mut verified = VerifiedBloom::new(filter);
+
+        for i in 0..500 {
+            plain.mark(&i);
+            verified.mark(&i);
+        }
+
+        assert!((0..2000).all(|i| verified.check(&i) == plain.check(&i)));
+        assert!(verified.name() == "standard");
+        assert!(verified.set_size() == 1024);
+        assert!(verified.into_inner() == plain);
+    }
+
+    #[test]
+    #[should_panic(expected = "false negative")]
+    fn catches_a_false_negative() {
+        let filter: DefaultStandardBloom<usize> = StandardBloom::new(1024, 16, 8);
+        let mut verified = VerifiedBloom::new(filter);
+        verified.mark(&100);
+
+        // Lose the item's bits without telling the exact set.
+        verified.filter.clear();
+        verified.check(&100);
+    }
+}